@@ -0,0 +1,87 @@
+//! Timings for scripts dominated by variable access, function calls and
+//! string comparisons. Run with `cargo bench`; each script runs a few times
+//! and the fastest run is reported.
+
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use jlox::interpreter::Interpreter;
+use jlox::natives::Capabilities;
+
+const RUNS: usize = 5;
+
+const SCRIPTS: &[(&str, &str)] = &[
+    (
+        "locals",
+        "var total = 0;
+         for (var i = 0; i < 200000; i = i + 1) {
+           var a = i; var b = a + 1; var c = b * 2;
+           total = total + c - a - b;
+         }",
+    ),
+    (
+        "globals in nested scopes",
+        "var x = 0; var y = 1; var z = 2;
+         for (var i = 0; i < 100000; i = i + 1) {
+           { { { x = x + y; y = z - y; z = x - z; } } }
+         }",
+    ),
+    (
+        "calls",
+        "fun add(a, b) { return a + b; }
+         fun fib(n) { if (n < 2) return n; return add(fib(n - 1), fib(n - 2)); }
+         fib(20);",
+    ),
+    (
+        "fibonacci",
+        "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+         fib(22);",
+    ),
+    (
+        "nested loops in a function",
+        "fun grid(size) {
+           var total = 0;
+           for (var i = 0; i < size; i = i + 1) {
+             for (var j = 0; j < size; j = j + 1) { total = total + i * j; }
+           }
+           return total;
+         }
+         grid(300);",
+    ),
+    (
+        "string constants",
+        "var hits = 0;
+         var kind = \"identifier\";
+         for (var i = 0; i < 100000; i = i + 1) {
+           if (kind == \"identifier\") hits = hits + 1;
+           if (kind == \"keyword\") hits = hits - 1;
+         }",
+    ),
+];
+
+fn time(source: &str) -> Duration {
+    let mut interpreter = Interpreter::with_capabilities(Capabilities { io: false }, vec![]);
+    interpreter.set_output(Rc::new(RefCell::new(io::sink())));
+
+    let start = Instant::now();
+    jlox::run(&mut interpreter, source).unwrap();
+    start.elapsed()
+}
+
+fn main() {
+    // Recursion runs deep enough to need the stack `jlox` itself uses.
+    thread::Builder::new()
+        .stack_size(64 * 1024 * 1024)
+        .spawn(|| {
+            for (name, source) in SCRIPTS {
+                let best = (0..RUNS).map(|_| time(source)).min().unwrap();
+                println!("{:<28} {:>10.2?}", name, best);
+            }
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
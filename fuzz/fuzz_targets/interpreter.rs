@@ -0,0 +1,40 @@
+#![no_main]
+
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+use jlox::interpreter::Interpreter;
+use jlox::limits::Limits;
+use jlox::natives::Capabilities;
+use libfuzzer_sys::fuzz_target;
+
+/// Scripts run on a thread of the size `jlox` itself uses, so inputs behave
+/// the way they would on the command line.
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    let source = source.to_string();
+    std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            // Without io, imports fail instead of reading the files inputs name.
+            let mut interpreter =
+                Interpreter::with_capabilities(Capabilities { io: false }, vec![]);
+            interpreter.set_output(Rc::new(RefCell::new(io::sink())));
+            // Keeps loops from running into libFuzzer's timeout.
+            interpreter.set_limits(Limits {
+                max_steps: Some(100_000),
+                max_string_length: Some(1 << 20),
+                ..Limits::default()
+            });
+            let _ = jlox::run(&mut interpreter, &source);
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use jlox::parser::Parser;
+use jlox::scanner::Scanner;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(source) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(tokens) = Scanner::new(source).scan_tokens() {
+        let _ = Parser::new(tokens).parse();
+    }
+});
@@ -0,0 +1,10 @@
+#![no_main]
+
+use jlox::scanner::Scanner;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(source) = std::str::from_utf8(data) {
+        let _ = Scanner::new(source).scan_tokens();
+    }
+});
@@ -0,0 +1,339 @@
+use crate::expr::{Expr, LiteralValue};
+use crate::scanner::Token;
+use crate::stmt::Stmt;
+
+/// Just enough of JSON to describe a syntax tree, or a tool's report.
+pub(crate) enum Json {
+    Null,
+    Bool(bool),
+    Number(f32),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(&'static str, Json)>),
+}
+
+impl Json {
+    pub(crate) fn render(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, 0);
+        out
+    }
+
+    fn write(&self, out: &mut String, indent: usize) {
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(b) => out.push_str(&b.to_string()),
+            Json::Number(x) => out.push_str(&x.to_string()),
+            Json::Str(s) => write_string(out, s),
+            Json::Array(items) if items.is_empty() => out.push_str("[]"),
+            Json::Array(items) => {
+                out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, indent + 1);
+                    item.write(out, indent + 1);
+                }
+                newline(out, indent);
+                out.push(']');
+            }
+            Json::Object(fields) => {
+                out.push('{');
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        out.push(',');
+                    }
+                    newline(out, indent + 1);
+                    write_string(out, key);
+                    out.push_str(": ");
+                    value.write(out, indent + 1);
+                }
+                newline(out, indent);
+                out.push('}');
+            }
+        }
+    }
+}
+
+fn newline(out: &mut String, indent: usize) {
+    out.push('\n');
+    out.push_str(&"  ".repeat(indent));
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn node(kind: &str, mut fields: Vec<(&'static str, Json)>) -> Json {
+    fields.insert(0, ("type", Json::Str(kind.to_string())));
+    Json::Object(fields)
+}
+
+fn name(token: &Token) -> Json {
+    Json::Str(token.lexeme.to_string())
+}
+
+fn line(token: &Token) -> Json {
+    Json::Number(token.line_number as f32)
+}
+
+fn literal(value: &LiteralValue) -> Json {
+    match value {
+        LiteralValue::Number(x) => Json::Number(*x),
+        LiteralValue::StringValue(s) => Json::Str(s.to_string()),
+        LiteralValue::True => Json::Bool(true),
+        LiteralValue::False => Json::Bool(false),
+        LiteralValue::Nil => Json::Null,
+        other => Json::Str(other.to_string()),
+    }
+}
+
+fn statements(stmts: &[Stmt]) -> Json {
+    Json::Array(stmts.iter().map(stmt).collect())
+}
+
+fn expr(expr: &Expr) -> Json {
+    match expr {
+        Expr::Assign {
+            name: target,
+            value,
+            ..
+        } => node(
+            "Assign",
+            vec![
+                ("name", name(target)),
+                ("line", line(target)),
+                ("value", self::expr(value)),
+            ],
+        ),
+        Expr::Binary {
+            left,
+            operator,
+            right,
+        } => node(
+            "Binary",
+            vec![
+                ("operator", name(operator)),
+                ("line", line(operator)),
+                ("left", self::expr(left)),
+                ("right", self::expr(right)),
+            ],
+        ),
+        Expr::Call {
+            callee,
+            paren,
+            arguments,
+        } => node(
+            "Call",
+            vec![
+                ("line", line(paren)),
+                ("callee", self::expr(callee)),
+                (
+                    "arguments",
+                    Json::Array(arguments.iter().map(self::expr).collect()),
+                ),
+            ],
+        ),
+        Expr::Get {
+            object,
+            name: member,
+        } => node(
+            "Get",
+            vec![
+                ("name", name(member)),
+                ("line", line(member)),
+                ("object", self::expr(object)),
+            ],
+        ),
+        Expr::Grouping { expression } => {
+            node("Grouping", vec![("expression", self::expr(expression))])
+        }
+        Expr::Literal { value, .. } => node("Literal", vec![("value", literal(value))]),
+        Expr::Unary { operator, right } => node(
+            "Unary",
+            vec![
+                ("operator", name(operator)),
+                ("line", line(operator)),
+                ("right", self::expr(right)),
+            ],
+        ),
+        Expr::Variable { name: variable, .. } => node(
+            "Variable",
+            vec![("name", name(variable)), ("line", line(variable))],
+        ),
+    }
+}
+
+fn stmt(stmt: &Stmt) -> Json {
+    match stmt {
+        Stmt::Block { statements } => {
+            node("Block", vec![("statements", self::statements(statements))])
+        }
+        Stmt::Break { keyword } => node("Break", vec![("line", line(keyword))]),
+        Stmt::Continue { keyword } => node("Continue", vec![("line", line(keyword))]),
+        Stmt::Expression { expression } => {
+            node("Expression", vec![("expression", expr(expression))])
+        }
+        Stmt::Function {
+            name: function,
+            params,
+            body,
+        } => node(
+            "Function",
+            vec![
+                ("name", name(function)),
+                ("line", line(function)),
+                ("params", Json::Array(params.iter().map(name).collect())),
+                ("body", statements(body)),
+            ],
+        ),
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => node(
+            "If",
+            vec![
+                ("condition", expr(condition)),
+                ("then", self::stmt(then_branch)),
+                (
+                    "else",
+                    else_branch.as_ref().map_or(Json::Null, |s| self::stmt(s)),
+                ),
+            ],
+        ),
+        Stmt::Import { path, name: alias } => node(
+            "Import",
+            vec![
+                ("path", Json::Str(path.lexeme.trim_matches('"').to_string())),
+                ("name", name(alias)),
+                ("line", line(path)),
+            ],
+        ),
+        Stmt::Print { expression } => node("Print", vec![("expression", expr(expression))]),
+        Stmt::Return { keyword, value } => node(
+            "Return",
+            vec![("line", line(keyword)), ("value", expr(value))],
+        ),
+        Stmt::Test { name, body } => node(
+            "Test",
+            vec![
+                ("name", Json::Str(name.lexeme.trim_matches('"').to_string())),
+                ("line", line(name)),
+                ("body", statements(body)),
+            ],
+        ),
+        Stmt::Throw { keyword, value } => node(
+            "Throw",
+            vec![("line", line(keyword)), ("value", expr(value))],
+        ),
+        Stmt::Try {
+            body,
+            catch,
+            finally,
+        } => node(
+            "Try",
+            vec![
+                ("body", statements(body)),
+                (
+                    "catch",
+                    catch.as_ref().map_or(Json::Null, |(variable, handler)| {
+                        Json::Object(vec![
+                            ("name", name(variable)),
+                            ("body", statements(handler)),
+                        ])
+                    }),
+                ),
+                (
+                    "finally",
+                    finally.as_ref().map_or(Json::Null, |s| statements(s)),
+                ),
+            ],
+        ),
+        Stmt::Var {
+            name: variable,
+            initializer,
+        } => node(
+            "Var",
+            vec![
+                ("name", name(variable)),
+                ("line", line(variable)),
+                ("initializer", expr(initializer)),
+            ],
+        ),
+        Stmt::While {
+            condition,
+            body,
+            increment,
+        } => node(
+            "While",
+            vec![
+                ("condition", expr(condition)),
+                ("body", self::stmt(body)),
+                ("increment", increment.as_ref().map_or(Json::Null, expr)),
+            ],
+        ),
+    }
+}
+
+/// The whole program as an indented JSON array of statement nodes. Every
+/// node has a `type`; nodes built around a token also carry its `line`.
+pub fn to_json(stmts: &[Stmt]) -> String {
+    statements(stmts).render()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    #[test]
+    fn serializes_statements_and_expressions() {
+        let tokens = Scanner::new("var a = \"q\\\";\nprint -a;")
+            .scan_tokens()
+            .unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+
+        assert_eq!(
+            to_json(&stmts),
+            r#"[
+  {
+    "type": "Var",
+    "name": "a",
+    "line": 1,
+    "initializer": {
+      "type": "Literal",
+      "value": "q\\"
+    }
+  },
+  {
+    "type": "Print",
+    "expression": {
+      "type": "Unary",
+      "operator": "-",
+      "line": 2,
+      "right": {
+        "type": "Variable",
+        "name": "a",
+        "line": 2
+      }
+    }
+  }
+]"#
+        );
+    }
+}
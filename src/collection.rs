@@ -0,0 +1,170 @@
+//! Lists and maps, the containers scripts build with the `list` and `map`
+//! natives and get back from `json.parse`. Both are shared by reference:
+//! every copy of one sees changes made through the others, and two are
+//! equal only when they are the same container.
+
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::expr::LiteralValue;
+
+#[derive(Clone, Default)]
+pub struct List(Rc<RefCell<Vec<LiteralValue>>>);
+
+impl List {
+    pub fn new(items: Vec<LiteralValue>) -> Self {
+        Self(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn items(&self) -> Ref<'_, Vec<LiteralValue>> {
+        self.0.borrow()
+    }
+
+    pub fn items_mut(&self) -> RefMut<'_, Vec<LiteralValue>> {
+        self.0.borrow_mut()
+    }
+
+    /// Identifies the container, to spot one that contains itself.
+    pub fn id(&self) -> *const () {
+        Rc::as_ptr(&self.0).cast()
+    }
+}
+
+impl PartialEq for List {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Entries are kept sorted by key, so maps print and serialize the same way
+/// every run.
+#[derive(Clone, Default)]
+pub struct Map(Rc<RefCell<BTreeMap<String, LiteralValue>>>);
+
+impl Map {
+    pub fn new(entries: BTreeMap<String, LiteralValue>) -> Self {
+        Self(Rc::new(RefCell::new(entries)))
+    }
+
+    pub fn entries(&self) -> Ref<'_, BTreeMap<String, LiteralValue>> {
+        self.0.borrow()
+    }
+
+    pub fn entries_mut(&self) -> RefMut<'_, BTreeMap<String, LiteralValue>> {
+        self.0.borrow_mut()
+    }
+
+    /// Identifies the container, to spot one that contains itself.
+    pub fn id(&self) -> *const () {
+        Rc::as_ptr(&self.0).cast()
+    }
+}
+
+impl PartialEq for Map {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Display for List {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_list(f, self, &mut vec![])
+    }
+}
+
+impl fmt::Debug for List {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl fmt::Display for Map {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_map(f, self, &mut vec![])
+    }
+}
+
+impl fmt::Debug for Map {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+/// Writes `list` as `[1, "two"]`, with `[...]` in place of any container
+/// that is already being written further out.
+fn write_list(f: &mut fmt::Formatter, list: &List, open: &mut Vec<*const ()>) -> fmt::Result {
+    if open.contains(&list.id()) {
+        return write!(f, "[...]");
+    }
+
+    open.push(list.id());
+    write!(f, "[")?;
+    for (i, item) in list.items().iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write_item(f, item, open)?;
+    }
+    open.pop();
+    write!(f, "]")
+}
+
+fn write_map(f: &mut fmt::Formatter, map: &Map, open: &mut Vec<*const ()>) -> fmt::Result {
+    if open.contains(&map.id()) {
+        return write!(f, "{{...}}");
+    }
+
+    open.push(map.id());
+    write!(f, "{{")?;
+    for (i, (key, value)) in map.entries().iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{:?}: ", key)?;
+        write_item(f, value, open)?;
+    }
+    open.pop();
+    write!(f, "}}")
+}
+
+/// Strings are quoted inside containers, so `["a, b"]` reads as one item.
+fn write_item(
+    f: &mut fmt::Formatter,
+    value: &LiteralValue,
+    open: &mut Vec<*const ()>,
+) -> fmt::Result {
+    match value {
+        LiteralValue::List(list) => write_list(f, list, open),
+        LiteralValue::Map(map) => write_map(f, map, open),
+        LiteralValue::StringValue(s) => write!(f, "{:?}", s),
+        other => write!(f, "{}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn containers_that_contain_themselves_print() {
+        let list = List::new(vec![
+            LiteralValue::Number(1.0),
+            LiteralValue::StringValue("a".into()),
+        ]);
+        let map = Map::default();
+        map.entries_mut()
+            .insert("list".to_string(), LiteralValue::List(list.clone()));
+        map.entries_mut()
+            .insert("self".to_string(), LiteralValue::Map(map.clone()));
+        list.items_mut().push(LiteralValue::Map(map.clone()));
+
+        assert_eq!(
+            list.to_string(),
+            r#"[1, "a", {"list": [...], "self": {...}}]"#
+        );
+        assert_eq!(list, list.clone());
+        assert_ne!(list, List::new(vec![]));
+    }
+}
@@ -0,0 +1,280 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::rc::Rc;
+
+use crate::interpreter::{Hook, Interpreter};
+use crate::stmt::Stmt;
+
+/// Which lines of one script ran and which ways its conditions went.
+///
+/// Both are tracked per line, so two `if`s on the same line share their
+/// branch counts.
+pub struct Coverage {
+    pub file: String,
+    /// How often the statements on each line ran. Every line with a
+    /// statement is present, so lines that never ran have a count of 0.
+    pub lines: BTreeMap<usize, u64>,
+    /// How often the conditions of the `if`s and `while`s on each line
+    /// were true and false.
+    pub branches: BTreeMap<usize, [u64; 2]>,
+}
+
+impl Coverage {
+    /// Coverage of `stmts`, the program in `file`, before any of it ran.
+    pub fn new(file: &str, stmts: &[Stmt]) -> Self {
+        let mut coverage = Self {
+            file: file.to_string(),
+            lines: BTreeMap::new(),
+            branches: BTreeMap::new(),
+        };
+        coverage.declare(stmts);
+        coverage
+    }
+
+    fn declare(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            if let (false, Some(line)) = (matches!(stmt, Stmt::Block { .. }), stmt.line()) {
+                self.lines.insert(line, 0);
+            }
+
+            match stmt {
+                Stmt::Block { statements } => self.declare(statements),
+                Stmt::Function { body, .. } => self.declare(body),
+                Stmt::If {
+                    condition,
+                    then_branch,
+                    else_branch,
+                } => {
+                    self.branches.insert(condition.line(), [0, 0]);
+                    self.declare(std::slice::from_ref(then_branch));
+                    if let Some(else_branch) = else_branch {
+                        self.declare(std::slice::from_ref(else_branch));
+                    }
+                }
+                Stmt::While {
+                    condition, body, ..
+                } => {
+                    self.branches.insert(condition.line(), [0, 0]);
+                    self.declare(std::slice::from_ref(body));
+                }
+                Stmt::Try {
+                    body,
+                    catch,
+                    finally,
+                } => {
+                    self.declare(body);
+                    if let Some((_, handler)) = catch {
+                        self.declare(handler);
+                    }
+                    if let Some(finally) = finally {
+                        self.declare(finally);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether `interpreter` is running this script rather than a function
+    /// imported from another one.
+    fn watching(&self, interpreter: &Interpreter) -> bool {
+        interpreter
+            .frames()
+            .last()
+            .is_some_and(|frame| frame.file == self.file)
+    }
+
+    /// The coverage as an LCOV tracefile.
+    pub fn lcov(&self) -> String {
+        let mut out = format!("TN:\nSF:{}\n", self.file);
+
+        for (line, [taken, skipped]) in &self.branches {
+            let ran = self.lines.get(line).copied().unwrap_or_default() > 0;
+            for (branch, count) in [taken, skipped].into_iter().enumerate() {
+                let count = if ran {
+                    count.to_string()
+                } else {
+                    "-".to_string()
+                };
+                let _ = writeln!(out, "BRDA:{},0,{},{}", line, branch, count);
+            }
+        }
+        let (branches_hit, branches) = self.branch_totals();
+        let _ = writeln!(out, "BRF:{}\nBRH:{}", branches, branches_hit);
+
+        for (line, count) in &self.lines {
+            let _ = writeln!(out, "DA:{},{}", line, count);
+        }
+        let (lines_hit, lines) = self.line_totals();
+        let _ = writeln!(out, "LF:{}\nLH:{}", lines, lines_hit);
+
+        out.push_str("end_of_record\n");
+        out
+    }
+
+    /// Totals for the terminal, and the lines that never ran.
+    pub fn summary(&self) -> String {
+        let (lines_hit, lines) = self.line_totals();
+        let (branches_hit, branches) = self.branch_totals();
+        let mut out = format!(
+            "Coverage of {}\n  Lines:    {}\n  Branches: {}\n",
+            self.file,
+            ratio(lines_hit, lines),
+            ratio(branches_hit, branches)
+        );
+
+        let missed: Vec<usize> = self
+            .lines
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(line, _)| *line)
+            .collect();
+        if !missed.is_empty() {
+            let _ = writeln!(out, "  Not run:  {}", ranges(&missed));
+        }
+        out
+    }
+
+    fn line_totals(&self) -> (usize, usize) {
+        let hit = self.lines.values().filter(|count| **count > 0).count();
+        (hit, self.lines.len())
+    }
+
+    fn branch_totals(&self) -> (usize, usize) {
+        let hit = self
+            .branches
+            .values()
+            .flatten()
+            .filter(|count| **count > 0)
+            .count();
+        (hit, self.branches.len() * 2)
+    }
+}
+
+fn ratio(hit: usize, total: usize) -> String {
+    if total == 0 {
+        return "0/0".to_string();
+    }
+    format!(
+        "{}/{} ({:.1}%)",
+        hit,
+        total,
+        hit as f64 * 100.0 / total as f64
+    )
+}
+
+/// Sorted line numbers as `3, 7-9, 12`.
+fn ranges(lines: &[usize]) -> String {
+    let mut groups: Vec<(usize, usize)> = vec![];
+    for &line in lines {
+        match groups.last_mut() {
+            Some((_, end)) if *end + 1 == line => *end = line,
+            _ => groups.push((line, line)),
+        }
+    }
+    groups
+        .iter()
+        .map(|&(start, end)| match start == end {
+            true => start.to_string(),
+            false => format!("{}-{}", start, end),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Attached to an interpreter as its `Hook`, filling in coverage the
+/// caller keeps a handle to.
+pub struct Recorder(pub Rc<RefCell<Coverage>>);
+
+impl Hook for Recorder {
+    fn before_statement(&mut self, interpreter: &mut Interpreter, stmt: &Stmt) {
+        let mut coverage = self.0.borrow_mut();
+        if matches!(stmt, Stmt::Block { .. }) || !coverage.watching(interpreter) {
+            return;
+        }
+        if let Some(count) = stmt.line().and_then(|line| coverage.lines.get_mut(&line)) {
+            *count += 1;
+        }
+    }
+
+    fn after_condition(&mut self, interpreter: &Interpreter, stmt: &Stmt, taken: bool) {
+        let mut coverage = self.0.borrow_mut();
+        if !coverage.watching(interpreter) {
+            return;
+        }
+        if let Some(counts) = stmt
+            .line()
+            .and_then(|line| coverage.branches.get_mut(&line))
+        {
+            counts[usize::from(!taken)] += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    const SOURCE: &str = "fun sign(n) {\n  if (n < 0) {\n    return -1;\n  }\n  return 1;\n}\n\
+                          var i = 0;\nwhile (i < 2) {\n  sign(i);\n  i = i + 1;\n}\n\
+                          if (false)\n  print \"never\";\n";
+
+    fn cover(source: &str) -> Coverage {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+
+        let coverage = Rc::new(RefCell::new(Coverage::new("<script>", &stmts)));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_hook(Box::new(Recorder(coverage.clone())));
+        interpreter.interpret(stmts).unwrap();
+
+        drop(interpreter);
+        Rc::try_unwrap(coverage).ok().unwrap().into_inner()
+    }
+
+    #[test]
+    fn records_lines_and_branches() {
+        let coverage = cover(SOURCE);
+
+        let lines: Vec<(usize, u64)> = coverage.lines.iter().map(|(l, c)| (*l, *c)).collect();
+        assert_eq!(
+            lines,
+            [
+                (1, 1),
+                (2, 2),
+                (3, 0),
+                (5, 2),
+                (7, 1),
+                (8, 1),
+                (9, 2),
+                (10, 2),
+                (12, 1),
+                (13, 0)
+            ]
+        );
+        assert_eq!(coverage.branches[&2], [0, 2]);
+        assert_eq!(coverage.branches[&8], [2, 1]);
+        assert_eq!(coverage.branches[&12], [0, 1]);
+
+        assert_eq!(
+            coverage.summary(),
+            "Coverage of <script>\n  Lines:    8/10 (80.0%)\n  \
+             Branches: 4/6 (66.7%)\n  Not run:  3, 13\n"
+        );
+    }
+
+    #[test]
+    fn writes_lcov() {
+        let coverage = cover("var a = 1;\nif (a > 0) a = 2;\nfun f() {\n  while (false) {}\n}");
+
+        assert_eq!(
+            coverage.lcov(),
+            "TN:\nSF:<script>\n\
+             BRDA:2,0,0,1\nBRDA:2,0,1,0\nBRDA:4,0,0,-\nBRDA:4,0,1,-\nBRF:4\nBRH:1\n\
+             DA:1,1\nDA:2,2\nDA:3,1\nDA:4,0\nLF:4\nLH:3\nend_of_record\n"
+        );
+    }
+}
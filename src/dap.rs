@@ -0,0 +1,417 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::process;
+use std::rc::Rc;
+
+use serde_json::{json, Value};
+
+use crate::debugger::{self, Mode, Pause, Stepper};
+use crate::environment::Environment;
+use crate::expr::LiteralValue;
+use crate::interpreter::{Hook, Interpreter};
+use crate::lsp::{read_message, write_message};
+use crate::natives::Capabilities;
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::stmt::Stmt;
+use crate::Failure;
+
+/// A Lox program only ever has the one thread.
+const THREAD_ID: u64 = 1;
+
+/// `jlox dap`: serves the Debug Adapter Protocol on stdin and stdout for
+/// one launch of one program.
+pub fn run() -> Result<(), String> {
+    serve(
+        Box::new(io::BufReader::new(io::stdin())),
+        Box::new(io::stdout()),
+    )
+}
+
+pub fn serve(input: Box<dyn BufRead>, output: Box<dyn Write>) -> Result<(), String> {
+    let connection = Rc::new(RefCell::new(Connection {
+        input,
+        output,
+        seq: 0,
+    }));
+    let mut stepper = Stepper::new(Mode::Continue);
+
+    // Nothing runs until the client has launched and finished configuring.
+    let mut launch = None;
+    let mut configured = false;
+    while launch.is_none() || !configured {
+        let Some(request) = connection.borrow_mut().read()? else {
+            return Ok(());
+        };
+        let mut connection = connection.borrow_mut();
+        match command(&request) {
+            "initialize" => {
+                let capabilities = json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsEvaluateForHovers": true,
+                });
+                connection.respond(&request, capabilities)?;
+                connection.event("initialized", json!({}))?;
+            }
+            "launch" => {
+                launch = Some(request["arguments"].clone());
+                connection.respond(&request, Value::Null)?;
+            }
+            "configurationDone" => {
+                configured = true;
+                connection.respond(&request, Value::Null)?;
+            }
+            "disconnect" => return connection.respond(&request, Value::Null),
+            _ => connection.common(&request, &mut stepper)?,
+        }
+    }
+
+    let launch = launch.unwrap_or_default();
+    if launch["stopOnEntry"] == true {
+        stepper.mode = Mode::Step;
+    }
+    let exit_code = match launch_program(&launch, &connection, stepper) {
+        Ok(()) => 0,
+        Err(Failure::Exit(status)) => status,
+        Err(Failure::Error(err)) => {
+            let output = json!({ "category": "stderr", "output": format!("{}\n", err) });
+            connection.borrow_mut().event("output", output)?;
+            1
+        }
+    };
+
+    let mut connection = connection.borrow_mut();
+    connection.event("exited", json!({ "exitCode": exit_code }))?;
+    connection.event("terminated", json!({}))?;
+
+    // Answer whatever else the client asks until it hangs up.
+    while let Some(request) = connection.read()? {
+        match command(&request) {
+            "disconnect" => return connection.respond(&request, Value::Null),
+            "threads" => connection.respond(&request, json!({ "threads": [] }))?,
+            _ => connection.fail(&request, "The program has finished")?,
+        }
+    }
+    Ok(())
+}
+
+fn launch_program(
+    launch: &Value,
+    connection: &Rc<RefCell<Connection>>,
+    stepper: Stepper,
+) -> Result<(), Failure> {
+    let program = launch["program"]
+        .as_str()
+        .ok_or("launch needs a 'program' to run".to_string())?;
+    let args = launch["args"]
+        .as_array()
+        .map(|args| {
+            args.iter()
+                .filter_map(|arg| arg.as_str().map(str::to_string))
+                .collect()
+        })
+        .unwrap_or_default();
+    let source = fs::read_to_string(program)
+        .map_err(|err| format!("could not read '{}': {}", program, err))?;
+
+    let mut interpreter = Interpreter::with_capabilities(Capabilities::all(), args);
+    interpreter.set_file(Path::new(program));
+    interpreter.set_output(Rc::new(RefCell::new(Console {
+        connection: connection.clone(),
+        line: String::new(),
+    })));
+    interpreter.set_hook(Box::new(Session {
+        connection: connection.clone(),
+        stepper,
+        scopes: vec![],
+    }));
+
+    let tokens = Scanner::new(&source).scan_tokens()?;
+    let stmts = Parser::new(tokens).parse()?;
+    Ok(interpreter.interpret(stmts)?)
+}
+
+fn command(request: &Value) -> &str {
+    request["command"].as_str().unwrap_or("")
+}
+
+/// The client's end of the session: requests come in, responses and events
+/// go out.
+struct Connection {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    seq: u64,
+}
+
+impl Connection {
+    fn read(&mut self) -> Result<Option<Value>, String> {
+        read_message(&mut self.input)
+    }
+
+    fn send(&mut self, mut message: Value) -> Result<(), String> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.output, &message)
+    }
+
+    fn respond(&mut self, request: &Value, body: Value) -> Result<(), String> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "success": true,
+            "command": request["command"],
+            "body": body,
+        }))
+    }
+
+    fn fail(&mut self, request: &Value, message: &str) -> Result<(), String> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "success": false,
+            "command": request["command"],
+            "message": message,
+        }))
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> Result<(), String> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    /// Requests that mean the same whether or not the program is paused.
+    fn common(&mut self, request: &Value, stepper: &mut Stepper) -> Result<(), String> {
+        match command(request) {
+            // Each request carries every breakpoint in one source, and
+            // leaves those in other sources alone.
+            "setBreakpoints" => {
+                let Some(source) = request["arguments"]["source"]["path"].as_str() else {
+                    return self.fail(request, "setBreakpoints needs a source path");
+                };
+                let lines: Vec<usize> = request["arguments"]["breakpoints"]
+                    .as_array()
+                    .map(|breakpoints| {
+                        breakpoints
+                            .iter()
+                            .filter_map(|breakpoint| breakpoint["line"].as_u64())
+                            .map(|line| line as usize)
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                *stepper.breakpoints(source) = lines.iter().copied().collect();
+
+                let breakpoints: Vec<Value> = lines
+                    .iter()
+                    .map(|line| json!({ "verified": true, "line": line }))
+                    .collect();
+                self.respond(request, json!({ "breakpoints": breakpoints }))
+            }
+            "threads" => self.respond(
+                request,
+                json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] }),
+            ),
+            other => self.fail(request, &format!("Unsupported request '{}'", other)),
+        }
+    }
+}
+
+/// Sends what the program prints to the client as `output` events, a line
+/// at a time.
+struct Console {
+    connection: Rc<RefCell<Connection>>,
+    line: String,
+}
+
+impl Write for Console {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.push_str(&String::from_utf8_lossy(buf));
+        while let Some(end) = self.line.find('\n') {
+            let line: String = self.line.drain(..=end).collect();
+            let output = json!({ "category": "stdout", "output": line });
+            self.connection
+                .borrow_mut()
+                .event("output", output)
+                .map_err(io::Error::other)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The debugger side of a launched program, attached as its `Hook`.
+struct Session {
+    connection: Rc<RefCell<Connection>>,
+    stepper: Stepper,
+    /// The scopes of the current pause, innermost first. A scope's
+    /// `variablesReference` is its position here plus one.
+    scopes: Vec<Rc<RefCell<Environment>>>,
+}
+
+impl Hook for Session {
+    fn before_statement(&mut self, interpreter: &mut Interpreter, stmt: &Stmt) {
+        let Some(pause) = self.stepper.check(interpreter, stmt) else {
+            return;
+        };
+        if self.pause(interpreter, &pause).is_err() {
+            // The client is gone; there is no one left to pause for.
+            self.stepper.mode = Mode::Detached;
+        }
+    }
+
+    fn after_condition(&mut self, _interpreter: &Interpreter, stmt: &Stmt, _taken: bool) {
+        self.stepper.after_condition(stmt);
+    }
+}
+
+impl Session {
+    fn pause(&mut self, interpreter: &mut Interpreter, pause: &Pause) -> Result<(), String> {
+        let reason = match (pause.breakpoint, self.scopes.is_empty()) {
+            (true, _) => "breakpoint",
+            (false, true) => "entry",
+            (false, false) => "step",
+        };
+        self.scopes = debugger::scopes(interpreter);
+        self.connection.borrow_mut().event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        )?;
+
+        loop {
+            // Not borrowed for long: evaluating an expression may print.
+            let Some(request) = self.connection.borrow_mut().read()? else {
+                self.stepper.mode = Mode::Detached;
+                return Ok(());
+            };
+            let arguments = &request["arguments"];
+
+            let resume = match command(&request) {
+                "continue" => Some(Mode::Continue),
+                "next" => Some(Mode::Next(pause.depth)),
+                "stepIn" => Some(Mode::Step),
+                "stepOut" => Some(Mode::Finish(pause.depth)),
+                _ => None,
+            };
+            if let Some(mode) = resume {
+                self.stepper.mode = mode;
+                return self
+                    .connection
+                    .borrow_mut()
+                    .respond(&request, json!({ "allThreadsContinued": true }));
+            }
+
+            let body = match command(&request) {
+                "stackTrace" => stack_trace(interpreter, pause.line),
+                "scopes" => self.scope_list(arguments["frameId"].as_u64().unwrap_or(0)),
+                "variables" => {
+                    let reference = arguments["variablesReference"].as_u64().unwrap_or(0);
+                    self.variables(reference as usize)
+                }
+                "evaluate" => {
+                    let expression = arguments["expression"].as_str().unwrap_or("");
+                    match debugger::evaluate(interpreter, expression) {
+                        Ok(value) => json!({ "result": display(&value), "variablesReference": 0 }),
+                        Err(msg) => {
+                            self.connection.borrow_mut().fail(&request, &msg)?;
+                            continue;
+                        }
+                    }
+                }
+                "disconnect" => {
+                    self.connection
+                        .borrow_mut()
+                        .respond(&request, Value::Null)?;
+                    process::exit(0);
+                }
+                _ => {
+                    let mut connection = self.connection.borrow_mut();
+                    connection.common(&request, &mut self.stepper)?;
+                    continue;
+                }
+            };
+            self.connection.borrow_mut().respond(&request, body)?;
+        }
+    }
+
+    /// Only the innermost frame's local scopes are reachable; every frame
+    /// can see the globals.
+    fn scope_list(&self, frame: u64) -> Value {
+        let last = self.scopes.len().saturating_sub(1);
+        let scopes: Vec<Value> = self
+            .scopes
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| frame == 0 || *index == last)
+            .map(|(index, _)| {
+                let name = match index {
+                    _ if index == last => "Globals".to_string(),
+                    0 => "Locals".to_string(),
+                    _ => format!("Enclosing {}", index),
+                };
+                json!({
+                    "name": name,
+                    "variablesReference": index + 1,
+                    "expensive": false,
+                })
+            })
+            .collect();
+        json!({ "scopes": scopes })
+    }
+
+    fn variables(&self, reference: usize) -> Value {
+        let Some(scope) = reference.checked_sub(1).and_then(|i| self.scopes.get(i)) else {
+            return json!({ "variables": [] });
+        };
+
+        let scope = scope.borrow();
+        let mut values = scope.values();
+        values.sort_by_key(|(name, _)| name.as_str());
+        let variables: Vec<Value> = values
+            .into_iter()
+            .map(|(name, value)| {
+                json!({
+                    "name": name.as_str(),
+                    "value": display(value),
+                    "type": value.to_type(),
+                    "variablesReference": 0,
+                })
+            })
+            .collect();
+        json!({ "variables": variables })
+    }
+}
+
+fn stack_trace(interpreter: &Interpreter, line: usize) -> Value {
+    let frames = interpreter.frames();
+    let frames: Vec<Value> = frames
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(id, frame)| {
+            let name = Path::new(&frame.file)
+                .file_name()
+                .map_or(frame.file.clone(), |name| {
+                    name.to_string_lossy().to_string()
+                });
+            json!({
+                "id": id,
+                "name": frame.function,
+                "line": if id == 0 { line } else { frame.line },
+                "column": 1,
+                "source": { "name": name, "path": frame.file },
+            })
+        })
+        .collect();
+    json!({ "stackFrames": frames, "totalFrames": frames.len() })
+}
+
+/// Values as they would be written in Lox, so strings show their quotes.
+fn display(value: &LiteralValue) -> String {
+    match value {
+        LiteralValue::StringValue(s) => format!("{:?}", s),
+        other => other.to_string(),
+    }
+}
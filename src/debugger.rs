@@ -0,0 +1,631 @@
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::process;
+use std::ptr;
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::expr::LiteralValue;
+use crate::interpreter::{Hook, Interpreter};
+use crate::parser::Parser;
+use crate::resolver;
+use crate::scanner::Scanner;
+use crate::stmt::Stmt;
+
+const HELP: &str = "\
+Commands:
+  break [[file:]line]
+                    Set a breakpoint, in this file unless one is named, or
+                    list them (b)
+  delete [file:]line
+                    Remove a breakpoint (d)
+  continue          Run to the next breakpoint (c)
+  step              Run to the next statement, entering calls (s)
+  next              Run to the next statement in this function (n)
+  finish            Run until this function returns (f)
+  backtrace         Show the call stack (bt)
+  list              Show the source around this line (l)
+  scopes            List the scopes visible from here
+  vars [scope]      Show the variables in a scope, innermost is 0 (v)
+  print <expr>      Evaluate an expression here (p)
+  set [scope] <name> = <expr>
+                    Change a variable, in the given scope or the nearest one
+  quit              Stop the program (q)";
+
+/// When to pause next, besides at breakpoints.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Step,
+    /// Pause at the next statement at or above this call depth.
+    Next(usize),
+    /// Pause at the next statement above this call depth.
+    Finish(usize),
+    Continue,
+    /// Nobody is listening any more, so let the program finish.
+    Detached,
+}
+
+/// A statement the program is paused before.
+pub struct Pause {
+    pub line: usize,
+    /// How many calls are in progress, the script itself included.
+    pub depth: usize,
+    pub breakpoint: bool,
+}
+
+/// Decides where a program under a debugger pauses, for both `jlox debug`
+/// and `jlox dap`.
+pub struct Stepper {
+    /// Lines to pause on, by the canonical path of their file.
+    breakpoints: HashMap<PathBuf, BTreeSet<usize>>,
+    /// The canonical paths of the file names frames give.
+    paths: HashMap<String, PathBuf>,
+    pub mode: Mode,
+    /// Line and call depth of the last statement, so several statements on
+    /// one line only pause once each time they run.
+    last: (usize, usize),
+    /// The last statement seen, to tell a loop's first test of its
+    /// condition from the ones that start another pass.
+    current: *const Stmt,
+}
+
+impl Stepper {
+    pub fn new(mode: Mode) -> Self {
+        Self {
+            breakpoints: HashMap::new(),
+            paths: HashMap::new(),
+            mode,
+            last: (0, 0),
+            current: ptr::null(),
+        }
+    }
+
+    /// The lines to pause on in `file`, named as frames or the user name it.
+    pub fn breakpoints(&mut self, file: &str) -> &mut BTreeSet<usize> {
+        let path = canonical(&mut self.paths, file).clone();
+        self.breakpoints.entry(path).or_default()
+    }
+
+    /// Every breakpoint as `file:line`, in order.
+    pub fn list_breakpoints(&self) -> Vec<String> {
+        let mut files: Vec<_> = self.breakpoints.iter().collect();
+        files.sort();
+        files
+            .into_iter()
+            .flat_map(|(path, lines)| {
+                lines
+                    .iter()
+                    .map(move |line| format!("{}:{}", path.display(), line))
+            })
+            .collect()
+    }
+
+    /// Forgets the line last paused on when a loop goes round again, so a
+    /// loop written on one line pauses on each pass.
+    pub fn after_condition(&mut self, stmt: &Stmt) {
+        if matches!(stmt, Stmt::While { .. }) && !ptr::eq(self.current, stmt) {
+            self.last = (0, 0);
+        }
+    }
+
+    pub fn check(&mut self, interpreter: &Interpreter, stmt: &Stmt) -> Option<Pause> {
+        self.current = stmt;
+        if self.mode == Mode::Detached || matches!(stmt, Stmt::Block { .. }) {
+            return None;
+        }
+        let line = stmt.line()?;
+        let frame = interpreter.frames().last()?;
+
+        let depth = interpreter.frames().len();
+        if (line, depth) == self.last {
+            return None;
+        }
+        self.last = (line, depth);
+
+        let path = canonical(&mut self.paths, &frame.file);
+        let breakpoint = self
+            .breakpoints
+            .get(path)
+            .is_some_and(|lines| lines.contains(&line));
+        let stop = breakpoint
+            || match self.mode {
+                Mode::Step => true,
+                Mode::Next(from) => depth <= from,
+                Mode::Finish(from) => depth < from,
+                Mode::Continue | Mode::Detached => false,
+            };
+        stop.then_some(Pause {
+            line,
+            depth,
+            breakpoint,
+        })
+    }
+}
+
+/// `file` with links and relative parts resolved, so a breakpoint set on
+/// one name for a file holds for every other. Names that are not files,
+/// like `<script>`, stand for themselves.
+fn canonical<'a>(paths: &'a mut HashMap<String, PathBuf>, file: &str) -> &'a PathBuf {
+    paths
+        .entry(file.to_string())
+        .or_insert_with(|| fs::canonicalize(file).unwrap_or_else(|_| PathBuf::from(file)))
+}
+
+/// Splits a breakpoint given as `[file:]line`, in `file` unless it names
+/// another.
+fn breakpoint<'a>(spec: &'a str, file: &'a str) -> Option<(&'a str, usize)> {
+    let (file, line) = spec.rsplit_once(':').unwrap_or((file, spec));
+    Some((file, line.parse().ok()?))
+}
+
+/// An interactive debugger, attached to an interpreter as its `Hook`. It
+/// pauses before the first statement so breakpoints can be set.
+pub struct Debugger {
+    source: Vec<String>,
+    /// Lines of the imported files paused in so far, keyed by the file name
+    /// their frames give.
+    imported: HashMap<String, Vec<String>>,
+    /// The imported file the program is paused in, or `None` for the script.
+    file: Option<String>,
+    stepper: Stepper,
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+}
+
+impl Debugger {
+    pub fn new(source: &str, input: Box<dyn BufRead>, output: Box<dyn Write>) -> Self {
+        Self {
+            source: source.lines().map(str::to_string).collect(),
+            imported: HashMap::new(),
+            file: None,
+            stepper: Stepper::new(Mode::Step),
+            input,
+            output,
+        }
+    }
+
+    fn pause(&mut self, interpreter: &mut Interpreter, line: usize, depth: usize) {
+        let file = match interpreter.frames().last().cloned() {
+            Some(frame) => {
+                self.say(format!("{}:{} in {}", frame.file, line, frame.function));
+                self.show(interpreter, frame.file.clone());
+                frame.file
+            }
+            None => String::new(),
+        };
+        self.list(line, 0);
+
+        loop {
+            let _ = write!(self.output, "(jlox) ");
+            let _ = self.output.flush();
+
+            let mut command = String::new();
+            if !matches!(self.input.read_line(&mut command), Ok(n) if n > 0) {
+                self.stepper.mode = Mode::Detached;
+                return;
+            }
+            let command = command.trim();
+            let (name, rest) = command.split_once(' ').unwrap_or((command, ""));
+            let rest = rest.trim();
+
+            match name {
+                "" => {}
+                "c" | "continue" | "s" | "step" | "n" | "next" | "f" | "finish" => {
+                    self.stepper.mode = match name {
+                        "c" | "continue" => Mode::Continue,
+                        "s" | "step" => Mode::Step,
+                        "n" | "next" => Mode::Next(depth),
+                        _ => Mode::Finish(depth),
+                    };
+                    return;
+                }
+                "b" | "break" if rest.is_empty() => {
+                    let breakpoints = self.stepper.list_breakpoints();
+                    self.say(format!("Breakpoints: {}", breakpoints.join(", ")));
+                }
+                "b" | "break" => match breakpoint(rest, &file) {
+                    Some((file, line)) => {
+                        self.stepper.breakpoints(file).insert(line);
+                        self.say(format!("Breakpoint at {}:{}", file, line));
+                    }
+                    None => self.say(format!("Expected [file:]line but got '{}'", rest)),
+                },
+                "d" | "delete" => match breakpoint(rest, &file) {
+                    Some((file, line)) if self.stepper.breakpoints(file).remove(&line) => {
+                        self.say(format!("Deleted breakpoint at {}:{}", file, line))
+                    }
+                    _ => self.say(format!("No breakpoint at {}", rest)),
+                },
+                "bt" | "backtrace" => {
+                    let mut frames = interpreter.frames().to_vec();
+                    if let Some(innermost) = frames.last_mut() {
+                        innermost.line = line;
+                    }
+                    for frame in frames.iter().rev() {
+                        self.say(format!("  {}", frame));
+                    }
+                }
+                "l" | "list" => self.list(line, 3),
+                "scopes" => {
+                    let scopes = scopes(interpreter);
+                    let count = scopes.len();
+                    for (index, scope) in scopes.iter().enumerate() {
+                        let kind = if index + 1 == count {
+                            "globals"
+                        } else {
+                            "local"
+                        };
+                        let size = scope.borrow().values().len();
+                        self.say(format!("#{} {} ({} variables)", index, kind, size));
+                    }
+                }
+                "v" | "vars" => match self.scope(interpreter, rest) {
+                    Ok(scope) => {
+                        let scope = scope.borrow();
+                        let mut values = scope.values();
+                        values.sort_by_key(|(name, _)| name.as_str());
+                        for (name, value) in values {
+                            self.say(format!("{} = {}", name, value));
+                        }
+                    }
+                    Err(msg) => self.say(msg),
+                },
+                "p" | "print" => match evaluate(interpreter, rest) {
+                    Ok(value) => self.say(value.to_string()),
+                    Err(msg) => self.say(msg),
+                },
+                "set" => {
+                    if let Err(msg) = self.set(interpreter, rest) {
+                        self.say(msg);
+                    }
+                }
+                "h" | "help" => self.say(HELP.to_string()),
+                "q" | "quit" => {
+                    let _ = self.output.flush();
+                    process::exit(0);
+                }
+                other => self.say(format!(
+                    "Unknown command '{}'. Type 'help' for a list.",
+                    other
+                )),
+            }
+        }
+    }
+
+    fn say(&mut self, text: String) {
+        let _ = writeln!(self.output, "{}", text);
+    }
+
+    /// Lists `file` from now on: the script, or a module a function called
+    /// from it was imported from, read the first time it is needed.
+    fn show(&mut self, interpreter: &Interpreter, file: String) {
+        if file == interpreter.frames()[0].file {
+            self.file = None;
+            return;
+        }
+        self.imported.entry(file.clone()).or_insert_with(|| {
+            let source = fs::read_to_string(&file).unwrap_or_default();
+            source.lines().map(str::to_string).collect()
+        });
+        self.file = Some(file);
+    }
+
+    /// Prints the lines within `context` of `line`, marking `line` itself.
+    fn list(&mut self, line: usize, context: usize) {
+        let source = match &self.file {
+            Some(file) => &self.imported[file],
+            None => &self.source,
+        };
+        let first = line.saturating_sub(context).max(1);
+        let last = (line + context).min(source.len());
+        let lines: Vec<String> = (first..=last)
+            .map(|number| {
+                let marker = if number == line { "=>" } else { "  " };
+                format!("{} {:>4}  {}", marker, number, source[number - 1])
+            })
+            .collect();
+        for text in lines {
+            self.say(text);
+        }
+    }
+
+    fn scope(
+        &self,
+        interpreter: &Interpreter,
+        index: &str,
+    ) -> Result<Rc<RefCell<Environment>>, String> {
+        let index = if index.is_empty() {
+            0
+        } else {
+            index
+                .parse::<usize>()
+                .map_err(|_| format!("Expected a scope number but got '{}'", index))?
+        };
+        scopes(interpreter)
+            .get(index)
+            .cloned()
+            .ok_or_else(|| format!("No scope #{}; see 'scopes'", index))
+    }
+
+    fn set(&mut self, interpreter: &mut Interpreter, command: &str) -> Result<(), String> {
+        let (scope, command) = match command.split_once(' ') {
+            Some((index, rest)) if index.parse::<usize>().is_ok() => {
+                (Some(self.scope(interpreter, index)?), rest)
+            }
+            _ => (None, command),
+        };
+        let (name, expression) = command
+            .split_once('=')
+            .ok_or("Usage: set [scope] <name> = <expr>")?;
+        let name = name.trim();
+        let value = evaluate(interpreter, expression)?;
+
+        let assigned = match scope {
+            Some(scope) if scope.borrow().contains(name) => {
+                scope.borrow_mut().define(name.to_string(), value.clone());
+                true
+            }
+            Some(_) => false,
+            None => interpreter
+                .environment
+                .borrow_mut()
+                .assign(name, value.clone()),
+        };
+        if !assigned {
+            return Err(format!("No variable '{}' in that scope", name));
+        }
+
+        self.say(format!("{} = {}", name, value));
+        Ok(())
+    }
+}
+
+impl Hook for Debugger {
+    fn before_statement(&mut self, interpreter: &mut Interpreter, stmt: &Stmt) {
+        if let Some(pause) = self.stepper.check(interpreter, stmt) {
+            self.pause(interpreter, pause.line, pause.depth);
+        }
+    }
+
+    fn after_condition(&mut self, _interpreter: &Interpreter, stmt: &Stmt, _taken: bool) {
+        self.stepper.after_condition(stmt);
+    }
+}
+
+/// The scopes visible from where the interpreter is, innermost first,
+/// leaving out the built-in functions.
+pub fn scopes(interpreter: &Interpreter) -> Vec<Rc<RefCell<Environment>>> {
+    let mut scopes = vec![interpreter.environment.clone()];
+    loop {
+        let enclosing = scopes.last().and_then(|scope| scope.borrow().enclosing());
+        match enclosing {
+            Some(scope) => scopes.push(scope),
+            None => break,
+        }
+    }
+    scopes.pop();
+    scopes
+}
+
+/// Runs `source` where the interpreter is paused, giving back the value of
+/// a final expression.
+///
+/// Declarations are only allowed at the top level: a local scope holds its
+/// variables in the slots the resolver gave them, and a new one would land
+/// in a slot code after it expects to be something else.
+pub fn evaluate(interpreter: &mut Interpreter, source: &str) -> Result<LiteralValue, String> {
+    let tokens = Scanner::new(source).scan_tokens()?;
+    let stmts = Parser::for_repl(tokens).parse()?;
+    if !interpreter.at_top_level() && resolver::declares(&stmts) {
+        return Err("Can not declare variables while paused in a local scope".to_string());
+    }
+    match interpreter.interpret_repl(stmts) {
+        Ok(value) => Ok(value.unwrap_or(LiteralValue::Nil)),
+        Err(exception) => Err(exception.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Output the test can read back after the debugger has been moved
+    /// into the interpreter.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const SOURCE: &str =
+        "fun add(a, b) {\n  var sum = a + b;\n  return sum;\n}\nvar x = add(1, 2);\nprint x;";
+
+    fn debug(commands: &str) -> (Vec<String>, Interpreter) {
+        debug_in(Interpreter::new(), SOURCE, commands)
+    }
+
+    fn debug_in(
+        mut interpreter: Interpreter,
+        source: &str,
+        commands: &str,
+    ) -> (Vec<String>, Interpreter) {
+        let output = Shared::default();
+        let debugger = Debugger::new(
+            source,
+            Box::new(std::io::Cursor::new(commands.to_string())),
+            Box::new(output.clone()),
+        );
+
+        interpreter.set_hook(Box::new(debugger));
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        interpreter
+            .interpret(Parser::new(tokens).parse().unwrap())
+            .unwrap();
+
+        let output = String::from_utf8(output.0.borrow().clone()).unwrap();
+        let lines = output
+            .lines()
+            .map(|line| line.trim_start_matches("(jlox) ").to_string())
+            .filter(|line| !line.is_empty())
+            .collect();
+        (lines, interpreter)
+    }
+
+    fn stops(lines: &[String]) -> Vec<&str> {
+        lines
+            .iter()
+            .filter(|line| line.starts_with("=>"))
+            .map(String::as_str)
+            .collect()
+    }
+
+    #[test]
+    fn steps_into_over_and_out_of_calls() {
+        let (lines, _) = debug("step\nstep\nnext\nfinish\n");
+
+        assert_eq!(
+            stops(&lines),
+            [
+                "=>    1  fun add(a, b) {",
+                "=>    5  var x = add(1, 2);",
+                "=>    2    var sum = a + b;",
+                "=>    3    return sum;",
+                "=>    6  print x;",
+            ]
+        );
+        assert_eq!(lines[2], "<script>:5 in <script>");
+        assert_eq!(lines[4], "<script>:2 in add");
+    }
+
+    #[test]
+    fn inspects_and_modifies_paused_scopes() {
+        let commands = "break 3\ncontinue\nvars\nscopes\nbacktrace\nprint a * 10\n\
+                        set sum = 100\nset 1 x = 7\nset nope = 1\ncontinue\n";
+        let (lines, interpreter) = debug(commands);
+
+        let from_stop = &lines[lines.iter().position(|l| l.starts_with("=>    3")).unwrap() + 1..];
+        assert_eq!(
+            from_stop,
+            [
+                "a = 1",
+                "b = 2",
+                "sum = 3",
+                "#0 local (3 variables)",
+                "#1 globals (1 variables)",
+                "  at add (<script>:3)",
+                "  at <script> (<script>:5)",
+                "10",
+                "sum = 100",
+                "No variable 'x' in that scope",
+                "No variable 'nope' in that scope",
+            ]
+        );
+        assert_eq!(
+            interpreter.environment.borrow().get("x"),
+            Some(LiteralValue::Number(100.0))
+        );
+    }
+
+    #[test]
+    fn declarations_are_rejected_in_local_scopes() {
+        let commands = "break 3\ncontinue\nprint var extra = 1;\nprint sum\n\
+                        break 6\ncontinue\nprint var y = x + 1;\ncontinue\n";
+        let (lines, interpreter) = debug(commands);
+
+        let from_stop = &lines[lines.iter().position(|l| l.starts_with("=>    3")).unwrap() + 1..];
+        assert_eq!(
+            from_stop[..2],
+            [
+                "Can not declare variables while paused in a local scope",
+                "3"
+            ]
+        );
+        assert_eq!(
+            interpreter.environment.borrow().get("y"),
+            Some(LiteralValue::Number(4.0))
+        );
+    }
+
+    #[test]
+    fn lists_the_file_of_imported_functions() {
+        let dir = std::env::temp_dir().join("jlox_debugger_imports");
+        fs::create_dir_all(&dir).unwrap();
+        let module = "// Helpers.\nfun twice(n) {\n  var doubled = n * 2;\n  return doubled;\n}";
+        fs::write(dir.join("helpers.lox"), module).unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_file(&dir.join("main.lox"));
+
+        let source = "import \"helpers.lox\" as helpers;\nprint helpers.twice(2);\nprint \"done\";";
+        let (lines, _) = debug_in(interpreter, source, "step\nstep\nlist\nstep\nstep\n");
+
+        assert_eq!(
+            stops(&lines),
+            [
+                "=>    1  import \"helpers.lox\" as helpers;",
+                "=>    2  print helpers.twice(2);",
+                "=>    3    var doubled = n * 2;",
+                "=>    3    var doubled = n * 2;",
+                "=>    4    return doubled;",
+                "=>    3  print \"done\";",
+            ]
+        );
+        assert!(lines.contains(&"      2  fun twice(n) {".to_string()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn breakpoints_belong_to_one_file() {
+        let dir = std::env::temp_dir().join("jlox_debugger_breakpoints");
+        fs::create_dir_all(&dir).unwrap();
+        let module = "// Helpers.\nfun twice(n) {\n  var doubled = n * 2;\n  return doubled;\n}";
+        let helpers = dir.join("helpers.lox");
+        fs::write(&helpers, module).unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_file(&dir.join("main.lox"));
+
+        let source = "import \"helpers.lox\" as helpers;\nvar x = helpers.twice(2);\nprint x;";
+        let commands = format!(
+            "break 3\nbreak {}:4\ncontinue\ncontinue\ncontinue\n",
+            helpers.display()
+        );
+        let (lines, _) = debug_in(interpreter, source, &commands);
+
+        assert_eq!(
+            stops(&lines),
+            [
+                "=>    1  import \"helpers.lox\" as helpers;",
+                "=>    4    return doubled;",
+                "=>    3  print x;",
+            ]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn one_line_loops_pause_on_each_pass() {
+        let source = "var i = 0;\nwhile (i < 3) i = i + 1;\nprint i;";
+        let (lines, _) = debug_in(
+            Interpreter::new(),
+            source,
+            "break 2\ncontinue\nprint i\ncontinue\nprint i\ncontinue\nprint i\ncontinue\n",
+        );
+
+        let values: Vec<&str> = lines
+            .iter()
+            .filter(|line| line.parse::<f64>().is_ok())
+            .map(String::as_str)
+            .collect();
+        assert_eq!(values, ["0", "1", "2"]);
+        assert_eq!(stops(&lines).len(), 1 + 3);
+    }
+}
@@ -1,43 +1,157 @@
 use crate::expr::LiteralValue;
-use std::collections::HashMap;
+use crate::interner::{Symbol, SymbolMap};
+use std::cell::RefCell;
+use std::rc::Rc;
 
+/// The variables of one scope. Globals and built-ins are kept by name, so
+/// scripts can refer to them before they are declared, while locals sit in
+/// the slots the resolver gave them.
 pub struct Environment {
-    values: HashMap<String, LiteralValue>,
-    enclosing: Option<Box<Environment>>,
+    values: Values,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+enum Values {
+    Named(SymbolMap<LiteralValue>),
+    /// Declared in order, so a local's slot is its index. Names are kept
+    /// for the debugger and for expressions that were not resolved.
+    Slots(Vec<(Symbol, LiteralValue)>),
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Environment {
+    /// The built-ins, or any scope of its own that is looked up by name.
     pub fn new() -> Self {
         Self {
-            values: HashMap::new(),
+            values: Values::Named(SymbolMap::default()),
             enclosing: None,
         }
     }
 
-    pub fn define(&mut self, name: String, value: LiteralValue) {
-        self.values.insert(name, value);
+    /// The globals of a script, falling back on `builtins`.
+    pub fn global(builtins: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            values: Values::Named(SymbolMap::default()),
+            enclosing: Some(builtins),
+        }
     }
 
-    pub fn get(&self, name: &str) -> Option<&LiteralValue> {
-        let value = self.values.get(name);
+    /// A local scope: a block, a call or a `catch` handler.
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            values: Values::Slots(vec![]),
+            enclosing: Some(enclosing),
+        }
+    }
 
-        match (value, &self.enclosing) {
-            (Some(val), _) => Some(val),
-            (None, Some(env)) => env.get(name),
-            (None, None) => None,
+    /// The variables declared in this scope alone, in no particular order.
+    pub fn values(&self) -> Vec<(Symbol, &LiteralValue)> {
+        match &self.values {
+            Values::Named(values) => values.iter().map(|(name, value)| (*name, value)).collect(),
+            Values::Slots(slots) => slots.iter().map(|(name, value)| (*name, value)).collect(),
         }
     }
 
-    pub fn assign(&mut self, name: &str, value: LiteralValue) -> bool {
-        let old_value = self.values.get(name);
+    /// Whether `name` is declared in this scope, not counting enclosing ones.
+    pub fn contains(&self, name: impl Into<Symbol>) -> bool {
+        self.local(name.into()).is_some()
+    }
+
+    pub fn enclosing(&self) -> Option<Rc<RefCell<Environment>>> {
+        self.enclosing.clone()
+    }
 
-        match (old_value, &mut self.enclosing) {
-            (Some(_), _) => {
-                self.values.insert(name.to_string(), value);
-                true
+    /// Declares `name`, replacing any variable of that name in this scope.
+    /// A new local takes the next slot, as the resolver expects.
+    pub fn define(&mut self, name: impl Into<Symbol>, value: LiteralValue) {
+        let name = name.into();
+        match &mut self.values {
+            Values::Named(values) => {
+                values.insert(name, value);
             }
-            (None, Some(env)) => env.assign(name, value),
-            (None, None) => false,
+            Values::Slots(slots) => match slots.iter_mut().find(|(local, _)| *local == name) {
+                Some((_, old_value)) => *old_value = value,
+                None => slots.push((name, value)),
+            },
+        }
+    }
+
+    /// Looks `name` up by name, from this scope outwards.
+    pub fn get(&self, name: impl Into<Symbol>) -> Option<LiteralValue> {
+        let name = name.into();
+
+        match (self.local(name), &self.enclosing) {
+            (Some(val), _) => Some(val.clone()),
+            (None, Some(env)) => env.borrow().get(name),
+            (None, None) => None,
+        }
+    }
+
+    pub fn assign(&mut self, name: impl Into<Symbol>, value: LiteralValue) -> bool {
+        let name = name.into();
+
+        if let Some(old_value) = self.local_mut(name) {
+            *old_value = value;
+            return true;
+        }
+        match &self.enclosing {
+            Some(env) => env.borrow_mut().assign(name, value),
+            None => false,
+        }
+    }
+
+    /// Reads the local in `slot` of the scope `depth` levels out.
+    pub fn get_at(&self, depth: usize, slot: usize) -> Option<LiteralValue> {
+        if depth > 0 {
+            return self.enclosing.as_ref()?.borrow().get_at(depth - 1, slot);
+        }
+        match &self.values {
+            Values::Slots(slots) => slots.get(slot).map(|(_, value)| value.clone()),
+            Values::Named(_) => None,
+        }
+    }
+
+    pub fn assign_at(&mut self, depth: usize, slot: usize, value: LiteralValue) -> bool {
+        if depth > 0 {
+            return match &self.enclosing {
+                Some(env) => env.borrow_mut().assign_at(depth - 1, slot, value),
+                None => false,
+            };
+        }
+        match &mut self.values {
+            Values::Slots(slots) => match slots.get_mut(slot) {
+                Some((_, old_value)) => {
+                    *old_value = value;
+                    true
+                }
+                None => false,
+            },
+            Values::Named(_) => false,
+        }
+    }
+
+    fn local(&self, name: Symbol) -> Option<&LiteralValue> {
+        match &self.values {
+            Values::Named(values) => values.get(&name),
+            Values::Slots(slots) => slots
+                .iter()
+                .find(|(local, _)| *local == name)
+                .map(|(_, value)| value),
+        }
+    }
+
+    fn local_mut(&mut self, name: Symbol) -> Option<&mut LiteralValue> {
+        match &mut self.values {
+            Values::Named(values) => values.get_mut(&name),
+            Values::Slots(slots) => slots
+                .iter_mut()
+                .find(|(local, _)| *local == name)
+                .map(|(_, value)| value),
         }
     }
 }
@@ -48,6 +162,25 @@ mod tests {
 
     #[test]
     fn try_init() {
-        let environment = Environment::new();
+        let _environment = Environment::new();
+    }
+
+    #[test]
+    fn locals_take_slots_in_order() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        globals.borrow_mut().define("a", LiteralValue::Number(0.0));
+        let outer = Rc::new(RefCell::new(Environment::with_enclosing(globals)));
+        outer.borrow_mut().define("b", LiteralValue::Number(1.0));
+        outer.borrow_mut().define("c", LiteralValue::Number(2.0));
+        outer.borrow_mut().define("b", LiteralValue::Number(3.0));
+        let mut inner = Environment::with_enclosing(outer);
+
+        assert_eq!(inner.get_at(1, 0), Some(LiteralValue::Number(3.0)));
+        assert_eq!(inner.get_at(1, 1), Some(LiteralValue::Number(2.0)));
+        assert!(inner.assign_at(1, 1, LiteralValue::Nil));
+        assert_eq!(inner.get("c"), Some(LiteralValue::Nil));
+        assert_eq!(inner.get("a"), Some(LiteralValue::Number(0.0)));
+        assert_eq!(inner.get_at(0, 0), None);
+        assert_eq!(inner.get_at(2, 0), None);
     }
 }
@@ -0,0 +1,185 @@
+use std::fmt;
+
+use crate::expr::LiteralValue;
+use crate::limits::LimitExceeded;
+
+/// How many frames of a long traceback are printed at each end.
+const TRACE_EDGE: usize = 10;
+/// How many times an identical frame is printed before it is folded.
+const REPEAT_LIMIT: usize = 3;
+
+/// A function call in progress: which function, where it was declared and
+/// the line it is currently executing (or calling from).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    pub file: String,
+    /// The line the function is declared on, or 0 for the top level.
+    pub declared: usize,
+    pub line: usize,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at {} ({}:{})", self.function, self.file, self.line)
+    }
+}
+
+/// A Lox value in flight after a `throw` or a runtime error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exception {
+    pub value: LiteralValue,
+    pub line: usize,
+    /// The call stack when the exception left the function that raised it,
+    /// innermost frame first.
+    pub trace: Vec<Frame>,
+    /// Set when the script was stopped for going over its `Limits`, which
+    /// `catch` does not intercept.
+    pub limit: Option<LimitExceeded>,
+    /// Set when the script called `exit` with this status. Like a limit, it
+    /// is not intercepted by `catch`.
+    pub exit: Option<i32>,
+}
+
+impl Exception {
+    pub fn throw(value: LiteralValue, line: usize) -> Self {
+        Self {
+            value,
+            line,
+            trace: vec![],
+            limit: None,
+            exit: None,
+        }
+    }
+
+    /// A runtime error raised by the interpreter itself, which scripts see as
+    /// an error object with `message` and `line` members.
+    pub fn error(message: String, line: usize) -> Self {
+        Self::throw(LiteralValue::Error { message, line }, line)
+    }
+
+    pub fn limit(exceeded: LimitExceeded, line: usize) -> Self {
+        Self {
+            limit: Some(exceeded),
+            ..Self::error(exceeded.to_string(), line)
+        }
+    }
+
+    pub fn exit(status: i32, line: usize) -> Self {
+        Self {
+            exit: Some(status),
+            ..Self::error(format!("Exited with status {}", status), line)
+        }
+    }
+
+    /// Whether `catch` can intercept the exception.
+    pub fn catchable(&self) -> bool {
+        self.limit.is_none() && self.exit.is_none()
+    }
+
+    pub fn message(&self) -> String {
+        match &self.value {
+            LiteralValue::Error { message, .. } => message.clone(),
+            other => other.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.limit, &self.value) {
+            _ if self.exit.is_some() => write!(f, "{}", self.message())?,
+            (Some(LimitExceeded::Interrupted), _) => write!(f, "{}", self.message())?,
+            (Some(_), _) => write!(f, "Limit exceeded: {}", self.message())?,
+            (None, LiteralValue::Error { .. }) => write!(f, "Uncaught Error: {}", self.message())?,
+            (None, _) => write!(f, "Uncaught exception: {}", self.message())?,
+        }
+
+        for line in traceback(&self.trace) {
+            write!(f, "\n  {}", line)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Renders frames one per line, folding runs of identical frames and
+/// eliding the middle of very deep stacks.
+fn traceback(frames: &[Frame]) -> Vec<String> {
+    let mut lines = vec![];
+    let mut i = 0;
+
+    while i < frames.len() {
+        let run = frames[i..]
+            .iter()
+            .take_while(|frame| **frame == frames[i])
+            .count();
+
+        for frame in &frames[i..i + run.min(REPEAT_LIMIT)] {
+            lines.push(frame.to_string());
+        }
+        if run > REPEAT_LIMIT {
+            lines.push(format!(
+                "... previous frame repeated {} more times",
+                run - REPEAT_LIMIT
+            ));
+        }
+
+        i += run;
+    }
+
+    if lines.len() > 2 * TRACE_EDGE {
+        let omitted = lines.len() - 2 * TRACE_EDGE;
+        let tail = lines.split_off(lines.len() - TRACE_EDGE);
+        lines.truncate(TRACE_EDGE);
+        lines.push(format!("... {} more lines omitted ...", omitted));
+        lines.extend(tail);
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(function: &str, line: usize) -> Frame {
+        Frame {
+            function: function.to_string(),
+            file: "main.lox".to_string(),
+            declared: 1,
+            line,
+        }
+    }
+
+    #[test]
+    fn folds_repeated_frames() {
+        let mut trace = vec![frame("inner", 2)];
+        trace.extend(vec![frame("recurse", 5); 100]);
+        trace.push(frame("<script>", 9));
+
+        assert_eq!(
+            traceback(&trace),
+            vec![
+                "at inner (main.lox:2)",
+                "at recurse (main.lox:5)",
+                "at recurse (main.lox:5)",
+                "at recurse (main.lox:5)",
+                "... previous frame repeated 97 more times",
+                "at <script> (main.lox:9)",
+            ]
+        );
+    }
+
+    #[test]
+    fn elides_the_middle_of_deep_stacks() {
+        let trace: Vec<Frame> = (0..30).map(|line| frame("f", line)).collect();
+
+        let lines = traceback(&trace);
+
+        assert_eq!(lines.len(), 2 * TRACE_EDGE + 1);
+        assert_eq!(lines[TRACE_EDGE - 1], "at f (main.lox:9)");
+        assert_eq!(lines[TRACE_EDGE], "... 10 more lines omitted ...");
+        assert_eq!(lines[TRACE_EDGE + 1], "at f (main.lox:20)");
+    }
+}
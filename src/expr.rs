@@ -1,62 +1,155 @@
-use crate::environment::Environment;
+use std::fmt;
+use std::ops::Deref;
+use std::rc::Rc;
+
+use crate::collection::{List, Map};
+use crate::exception::Exception;
+use crate::function::LoxFunction;
+use crate::interner::Symbol;
+use crate::interpreter::Interpreter;
+use crate::limits;
+use crate::module::Module;
+use crate::natives::{NativeError, NativeFunction};
+use crate::resolver::Location;
 use crate::scanner;
 use crate::scanner::{Token, TokenType};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum LiteralValue {
     Number(f32),
-    StringValue(String),
+    StringValue(LoxString),
     True,
     False,
     Nil,
+    Callable(NativeFunction),
+    Function(Rc<LoxFunction>),
+    Namespace(Rc<Module>),
+    List(List),
+    Map(Map),
+    Error { message: String, line: usize },
+}
+
+/// The text of a Lox string. Strings written in the source are interned, so
+/// comparing two of them only compares their symbols, while strings built as
+/// a script runs are shared rather than copied.
+#[derive(Clone)]
+pub enum LoxString {
+    Constant(Symbol),
+    Owned(Rc<str>),
+}
+
+impl Deref for LoxString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            LoxString::Constant(symbol) => symbol.as_str(),
+            LoxString::Owned(s) => s,
+        }
+    }
+}
+
+impl PartialEq for LoxString {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (LoxString::Constant(a), LoxString::Constant(b)) => a == b,
+            _ => **self == **other,
+        }
+    }
+}
+
+impl PartialOrd for LoxString {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        (**self).partial_cmp(&**other)
+    }
+}
+
+impl From<String> for LoxString {
+    fn from(s: String) -> Self {
+        LoxString::Owned(s.into())
+    }
 }
 
-fn unwrap_as_f32(literal: Option<scanner::LiteralValue>) -> f32 {
+impl From<&str> for LoxString {
+    fn from(s: &str) -> Self {
+        LoxString::Owned(s.into())
+    }
+}
+
+impl fmt::Display for LoxString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl fmt::Debug for LoxString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", &**self)
+    }
+}
+
+fn unwrap_as_f32(literal: Option<scanner::LiteralValue>) -> Result<f32, String> {
     match literal {
-        Some(scanner::LiteralValue::IntValue(x)) => x as f32,
-        Some(scanner::LiteralValue::FloatValue(x)) => x as f32,
-        _ => panic!("Could not unwrap as f32"),
+        Some(scanner::LiteralValue::IntValue(x)) => Ok(x as f32),
+        Some(scanner::LiteralValue::FloatValue(x)) => Ok(x as f32),
+        _ => Err("Could not unwrap as f32".to_string()),
     }
 }
 
-fn unwrap_as_string(literal: Option<scanner::LiteralValue>) -> String {
+fn unwrap_as_string(literal: Option<scanner::LiteralValue>) -> Result<String, String> {
     match literal {
-        Some(scanner::LiteralValue::StringValue(s)) => s.clone(),
-        Some(scanner::LiteralValue::IdentifierValue(s)) => s.clone(),
-        _ => panic!("Could not unwrap as string"),
+        Some(scanner::LiteralValue::StringValue(s)) => Ok(s),
+        Some(scanner::LiteralValue::IdentifierValue(s)) => Ok(s),
+        _ => Err("Could not unwrap as string".to_string()),
     }
 }
 
 use LiteralValue::*;
 
-impl LiteralValue {
-    pub fn to_string(&self) -> String {
+impl std::fmt::Display for LiteralValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            LiteralValue::Number(x) => x.to_string(),
-            LiteralValue::StringValue(x) => x.clone(),
-            LiteralValue::True => "true".to_string(),
-            LiteralValue::False => "false".to_string(),
-            LiteralValue::Nil => "nil".to_string(),
+            LiteralValue::Number(x) => write!(f, "{}", x),
+            LiteralValue::StringValue(x) => write!(f, "{}", x),
+            LiteralValue::True => write!(f, "true"),
+            LiteralValue::False => write!(f, "false"),
+            LiteralValue::Nil => write!(f, "nil"),
+            LiteralValue::Callable(fun) => write!(f, "{:?}", fun),
+            LiteralValue::Function(fun) => write!(f, "{:?}", fun),
+            LiteralValue::Namespace(module) => write!(f, "{:?}", module),
+            LiteralValue::List(list) => write!(f, "{}", list),
+            LiteralValue::Map(map) => write!(f, "{}", map),
+            LiteralValue::Error { message, .. } => write!(f, "Error: {}", message),
         }
     }
+}
 
+impl LiteralValue {
     pub fn to_type(&self) -> &str {
         match self {
-            LiteralValue::Number(x) => "Number",
-            LiteralValue::StringValue(x) => "String",
+            LiteralValue::Number(_) => "Number",
+            LiteralValue::StringValue(_) => "String",
             LiteralValue::True | LiteralValue::False => "Boolean",
             LiteralValue::Nil => "Nil",
+            LiteralValue::Callable(_) => "Callable",
+            LiteralValue::Function(_) => "Function",
+            LiteralValue::Namespace(_) => "Module",
+            LiteralValue::List(_) => "List",
+            LiteralValue::Map(_) => "Map",
+            LiteralValue::Error { .. } => "Error",
         }
     }
 
-    pub fn from_token(token: Token) -> Self {
+    pub fn from_token(token: Token) -> Result<Self, String> {
         match token.token_type {
-            TokenType::Number => Self::Number(unwrap_as_f32(token.literal)),
-            TokenType::StringLit => Self::StringValue(unwrap_as_string(token.literal)),
-            TokenType::False => Self::False,
-            TokenType::True => Self::True,
-            TokenType::Nil => Self::Nil,
-            _ => panic!("Could not create LiteralValue from {:?}", token),
+            TokenType::Number => Ok(Self::Number(unwrap_as_f32(token.literal)?)),
+            TokenType::StringLit => Ok(Self::StringValue(LoxString::Constant(Symbol::intern(
+                &unwrap_as_string(token.literal)?,
+            )))),
+            TokenType::False => Ok(Self::False),
+            TokenType::True => Ok(Self::True),
+            TokenType::Nil => Ok(Self::Nil),
+            _ => Err(format!("Could not create LiteralValue from {:?}", token)),
         }
     }
 
@@ -68,6 +161,10 @@ impl LiteralValue {
         }
     }
 
+    pub fn is_truthy(&self) -> bool {
+        self.is_falsy() == False
+    }
+
     pub fn is_falsy(&self) -> LiteralValue {
         match self {
             Number(x) => {
@@ -87,6 +184,12 @@ impl LiteralValue {
             True => False,
             False => True,
             Nil => True,
+            Callable(_) => False,
+            Function(_) => False,
+            Namespace(_) => False,
+            LiteralValue::List(_) => False,
+            LiteralValue::Map(_) => False,
+            Error { .. } => False,
         }
     }
 }
@@ -95,17 +198,28 @@ pub enum Expr {
     Assign {
         name: Token,
         value: Box<Expr>,
+        location: Location,
     },
     Binary {
         left: Box<Expr>,
         operator: Token,
         right: Box<Expr>,
     },
+    Call {
+        callee: Box<Expr>,
+        paren: Token,
+        arguments: Vec<Expr>,
+    },
+    Get {
+        object: Box<Expr>,
+        name: Token,
+    },
     Grouping {
         expression: Box<Expr>,
     },
     Literal {
         value: LiteralValue,
+        line: usize,
     },
     Unary {
         operator: Token,
@@ -113,61 +227,193 @@ pub enum Expr {
     },
     Variable {
         name: Token,
+        location: Location,
     },
 }
 
-impl Expr {
-    pub fn to_string(&self) -> String {
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
-            Expr::Assign { name, value } => format!("({:?} = {:?})", name, value.to_string()),
-            Expr::Variable { name } => format!("(var {})", name.lexeme),
+            Expr::Assign { name, value, .. } => write!(f, "(= {} {})", name.lexeme, value),
+            Expr::Variable { name, .. } => write!(f, "(var {})", name.lexeme),
             Expr::Binary {
                 left,
                 operator,
                 right,
-            } => format!(
-                "({} {} {})",
-                operator.lexeme,
-                left.to_string(),
-                right.to_string()
-            ),
-            Expr::Grouping { expression } => format!("(group {})", (*expression).to_string()),
-            Expr::Literal { value } => format!("{}", value.to_string()),
-            Expr::Unary { operator, right } => {
-                let operator_str = operator.lexeme.clone();
-                let right_str = (*right).to_string();
-                format!("({} {})", operator_str, right_str)
+            } => write!(f, "({} {} {})", operator.lexeme, left, right),
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                write!(f, "(call {}", callee)?;
+                for argument in arguments {
+                    write!(f, " {}", argument)?;
+                }
+                write!(f, ")")
             }
+            Expr::Get { object, name } => write!(f, "(. {} {})", object, name.lexeme),
+            Expr::Grouping { expression } => write!(f, "(group {})", expression),
+            Expr::Literal { value, .. } => write!(f, "{}", value),
+            Expr::Unary { operator, right } => write!(f, "({} {})", operator.lexeme, right),
+        }
+    }
+}
+
+impl Expr {
+    /// The line the expression starts on.
+    pub fn line(&self) -> usize {
+        match self {
+            Expr::Assign { name, .. } => name.line_number,
+            Expr::Binary { left, .. } => left.line(),
+            Expr::Call { callee, .. } => callee.line(),
+            Expr::Get { object, .. } => object.line(),
+            Expr::Grouping { expression } => expression.line(),
+            Expr::Literal { line, .. } => *line,
+            Expr::Unary { operator, .. } => operator.line_number,
+            Expr::Variable { name, .. } => name.line_number,
         }
     }
 
-    pub fn evaluate(&self, environment: &mut Environment) -> Result<LiteralValue, String> {
+    pub fn evaluate(&self, interpreter: &mut Interpreter) -> Result<LiteralValue, Exception> {
         match self {
-            Expr::Assign { name, value } => {
-                let new_value = value.evaluate(environment)?;
-                let is_assigned = environment.assign(&name.lexeme, new_value.clone());
+            Expr::Assign {
+                name,
+                value,
+                location,
+            } => {
+                let new_value = value.evaluate(interpreter)?;
+                let is_assigned =
+                    interpreter.assign(name.lexeme.symbol(), *location, new_value.clone());
 
                 if is_assigned {
                     Ok(new_value)
                 } else {
-                    Err(format!("Variable '{}' has not been assigned", name.lexeme))
+                    Err(Exception::error(
+                        format!("Variable '{}' has not been assigned", name.lexeme),
+                        name.line_number,
+                    ))
                 }
             }
-            Expr::Variable { name } => match environment.get(&name.lexeme.clone()) {
-                Some(value) => Ok(value.clone()),
-                None => Err(format!("Variable '{}' has not been declared", name.lexeme)),
-            },
-            Expr::Literal { value } => Ok((*value).clone()),
-            Expr::Grouping { expression } => expression.evaluate(environment),
+            Expr::Variable { name, location } => {
+                match interpreter.lookup(name.lexeme.symbol(), *location) {
+                    Some(value) => Ok(value),
+                    None => Err(Exception::error(
+                        format!("Variable '{}' has not been declared", name.lexeme),
+                        name.line_number,
+                    )),
+                }
+            }
+            Expr::Literal { value, .. } => Ok((*value).clone()),
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => {
+                let callee = limits::with_stack(|| callee.evaluate(interpreter))?;
+
+                let mut values = vec![];
+                for argument in arguments {
+                    values.push(argument.evaluate(interpreter)?);
+                }
+
+                match callee {
+                    Function(fun) => {
+                        if values.len() != fun.params.len() {
+                            return Err(Exception::error(
+                                format!(
+                                    "{} expected {} arguments but got {}",
+                                    fun.name.lexeme,
+                                    fun.params.len(),
+                                    values.len()
+                                ),
+                                paren.line_number,
+                            ));
+                        }
+
+                        interpreter.call_function(&fun, values, paren.line_number)
+                    }
+                    Callable(fun) => {
+                        if values.len() != fun.arity {
+                            return Err(Exception::error(
+                                format!(
+                                    "{} expected {} arguments but got {}",
+                                    fun.name,
+                                    fun.arity,
+                                    values.len()
+                                ),
+                                paren.line_number,
+                            ));
+                        }
+
+                        let value = fun.call(&values).map_err(|err| match err {
+                            NativeError::Message(msg) => Exception::error(msg, paren.line_number),
+                            NativeError::Exit(status) => Exception::exit(status, paren.line_number),
+                        })?;
+                        if let StringValue(s) = &value {
+                            interpreter.check_string(s.len(), paren.line_number)?;
+                        }
+                        // Natives like `push` grow the containers they are given.
+                        for container in values.iter().chain([&value]) {
+                            interpreter.check_collection(container, paren.line_number)?;
+                        }
+                        Ok(value)
+                    }
+                    other => Err(Exception::error(
+                        format!("Can only call functions, not {}", other.to_type()),
+                        paren.line_number,
+                    )),
+                }
+            }
+            Expr::Get { object, name } => {
+                match limits::with_stack(|| object.evaluate(interpreter))? {
+                    Namespace(module) => match module.get(&name.lexeme) {
+                        Some(value) => Ok(value.clone()),
+                        None => Err(Exception::error(
+                            format!(
+                                "Module '{}' has no member '{}'",
+                                module.path.display(),
+                                name.lexeme
+                            ),
+                            name.line_number,
+                        )),
+                    },
+                    LiteralValue::Map(map) => match map.entries().get(name.lexeme.as_str()) {
+                        Some(value) => Ok(value.clone()),
+                        None => Err(Exception::error(
+                            format!("Map has no key '{}'", name.lexeme),
+                            name.line_number,
+                        )),
+                    },
+                    Error { message, line } => match name.lexeme.as_str() {
+                        "message" => Ok(StringValue(message.into())),
+                        "line" => Ok(Number(line as f32)),
+                        _ => Err(Exception::error(
+                            format!("Error has no member '{}'", name.lexeme),
+                            name.line_number,
+                        )),
+                    },
+                    other => Err(Exception::error(
+                        format!(
+                            "Only modules, maps and errors have members, not {}",
+                            other.to_type()
+                        ),
+                        name.line_number,
+                    )),
+                }
+            }
+            Expr::Grouping { expression } => expression.evaluate(interpreter),
             Expr::Unary { operator, right } => {
-                let right = right.evaluate(environment)?;
+                let right = right.evaluate(interpreter)?;
                 match (&right, operator.token_type) {
                     (Number(x), TokenType::Minus) => Ok(Number(-x)),
-                    (_, TokenType::Minus) => {
-                        Err(format!("Minus not implemented for {}", right.to_type()))
-                    }
+                    (_, TokenType::Minus) => Err(Exception::error(
+                        format!("Minus not implemented for {}", right.to_type()),
+                        operator.line_number,
+                    )),
                     (any, TokenType::Bang) => Ok(any.is_falsy()),
-                    (_, token_type) => Err(format!("{} is not a valid unary operator", token_type)),
+                    (_, token_type) => Err(Exception::error(
+                        format!("{} is not a valid unary operator", token_type),
+                        operator.line_number,
+                    )),
                 }
             }
             Expr::Binary {
@@ -175,12 +421,16 @@ impl Expr {
                 operator,
                 right,
             } => {
-                let left = left.evaluate(environment)?;
-                let right = right.evaluate(environment)?;
+                let left = limits::with_stack(|| left.evaluate(interpreter))?;
+                let right = right.evaluate(interpreter)?;
 
                 match (&left, operator.token_type, &right) {
                     (Number(x), TokenType::Plus, Number(y)) => Ok(Number(x + y)),
                     (Number(x), TokenType::Minus, Number(y)) => Ok(Number(x - y)),
+                    (Number(_), TokenType::Slash, Number(y)) if *y == 0.0 => Err(Exception::error(
+                        "Division by zero".to_string(),
+                        operator.line_number,
+                    )),
                     (Number(x), TokenType::Slash, Number(y)) => Ok(Number(x / y)),
                     (Number(x), TokenType::Star, Number(y)) => Ok(Number(x * y)),
                     (Number(x), TokenType::Greater, Number(y)) => {
@@ -194,7 +444,8 @@ impl Expr {
                         Ok(LiteralValue::from_bool(x <= y))
                     }
                     (StringValue(s1), TokenType::Plus, StringValue(s2)) => {
-                        Ok(StringValue(format!("{}{}", s1, s2)))
+                        interpreter.check_string(s1.len() + s2.len(), operator.line_number)?;
+                        Ok(StringValue(format!("{}{}", s1, s2).into()))
                     }
                     (x, TokenType::BangEqual, y) => Ok(LiteralValue::from_bool(x != y)),
                     (x, TokenType::EqualEqual, y) => Ok(LiteralValue::from_bool(x == y)),
@@ -210,53 +461,54 @@ impl Expr {
                     (StringValue(s1), TokenType::LessEqual, StringValue(s2)) => {
                         Ok(LiteralValue::from_bool(s1 <= s2))
                     }
-                    (Number(_), opr, StringValue(_)) => {
-                        Err(format!("'{}' can not operate on string and number", opr))
-                    }
-                    (StringValue(_), opr, Number(_)) => {
-                        Err(format!("'{}' can not operate on string and number", opr))
+                    (Number(_), opr, StringValue(_)) | (StringValue(_), opr, Number(_)) => {
+                        Err(Exception::error(
+                            format!("'{}' can not operate on string and number", opr),
+                            operator.line_number,
+                        ))
                     }
-                    (x, token_type, y) => Err(format!(
-                        "'{}' is not implemented for these {:?} and  {:?} operands",
-                        token_type, x, y
+                    (x, token_type, y) => Err(Exception::error(
+                        format!(
+                            "'{}' is not implemented for these {:?} and  {:?} operands",
+                            token_type, x, y
+                        ),
+                        operator.line_number,
                     )),
                 }
             }
-            _ => Err("Operands must be two numbers or two strings.".to_string()),
         }
     }
-
-    pub fn print(&self) {
-        println!("{}", self.to_string());
-    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::Expr::*;
-    use super::LiteralValue::*;
     use super::*;
     #[test]
     fn pretty_print_ast() {
         let minus_token = Token {
             token_type: TokenType::Minus,
-            lexeme: "-".to_string(),
+            lexeme: "-".into(),
             literal: None,
             line_number: 0,
+            column: 0,
         };
         let onetwothree = Literal {
             value: Number(123.0),
+            line: 0,
         };
         let group = Grouping {
             expression: Box::from(Literal {
                 value: Number(45.67),
+                line: 0,
             }),
         };
         let multi = Token {
             token_type: TokenType::Star,
-            lexeme: "*".to_string(),
+            lexeme: "*".into(),
             literal: None,
             line_number: 0,
+            column: 0,
         };
         let ast = Binary {
             left: Box::from(Unary {
@@ -270,4 +522,21 @@ mod tests {
         let result = ast.to_string();
         assert_eq!(result, "(* (- 123) (group 45.67))");
     }
+
+    #[test]
+    fn strings_compare_by_text() {
+        let constant = StringValue(LoxString::Constant(Symbol::intern("ab")));
+        let built = StringValue(format!("{}{}", "a", "b").into());
+
+        assert_eq!(
+            constant,
+            StringValue(LoxString::Constant(Symbol::intern("ab")))
+        );
+        assert_eq!(constant, built);
+        assert_ne!(
+            constant,
+            StringValue(LoxString::Constant(Symbol::intern("ba")))
+        );
+        assert!(LoxString::from("ab") < LoxString::Constant(Symbol::intern("b")));
+    }
 }
@@ -0,0 +1,276 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::parser::Parser;
+use crate::scanner::TokenType::*;
+use crate::scanner::{Scanner, Token};
+
+const INDENT: &str = "    ";
+
+/// Reprints `source` in the canonical layout: one statement per line,
+/// four-space indentation, single spaces around binary operators and at
+/// most one blank line in a row. Comments stay where they were.
+pub fn format_source(source: &str) -> Result<String, String> {
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens()?;
+    // Reflowing something that would not even parse only hides the error.
+    Parser::new(tokens.clone()).parse()?;
+
+    let mut items: Vec<Token> = tokens
+        .into_iter()
+        .filter(|token| token.token_type != Eof)
+        .collect();
+    items.extend(scanner.comments().iter().cloned());
+    items.sort_by_key(|token| (token.line_number, token.column));
+
+    let mut printer = Printer::default();
+    for item in &items {
+        printer.push(item);
+    }
+
+    let mut out = printer.out;
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+#[derive(Default)]
+struct Printer<'a> {
+    out: String,
+    indent: usize,
+    parens: usize,
+    /// The last thing written, comments included.
+    prev: Option<&'a Token>,
+    /// The last token written, comments excluded.
+    last: Option<&'a Token>,
+    /// Whether `last` was a prefix `-` or `!`.
+    unary: bool,
+    /// Whether the next item has to start on a fresh line.
+    newline: bool,
+}
+
+impl<'a> Printer<'a> {
+    fn push(&mut self, token: &'a Token) {
+        if let Some(prev) = self.prev {
+            match token.token_type {
+                Comment if token.line_number == end_line(prev) => self.out.push(' '),
+                Comment => self.break_line(prev, token),
+                RightBrace => {
+                    self.indent = self.indent.saturating_sub(1);
+                    if prev.token_type == LeftBrace {
+                        self.newline = false;
+                    } else {
+                        self.break_line(prev, token);
+                    }
+                }
+                Else | Catch | Finally if prev.token_type == RightBrace => {
+                    self.newline = false;
+                    self.out.push(' ');
+                }
+                _ if self.newline => self.break_line(prev, token),
+                _ if self.spaced(token) => self.out.push(' '),
+                _ => {}
+            }
+        }
+
+        self.out.push_str(&token.lexeme);
+
+        match token.token_type {
+            Comment | RightBrace => self.newline = true,
+            LeftBrace => {
+                self.indent += 1;
+                self.newline = true;
+            }
+            Semicolon if self.parens == 0 => self.newline = true,
+            LeftParen => self.parens += 1,
+            RightParen => self.parens = self.parens.saturating_sub(1),
+            _ => {}
+        }
+
+        if token.token_type != Comment {
+            self.unary = matches!(token.token_type, Minus | Bang) && starts_operand(self.last);
+            self.last = Some(token);
+        }
+        self.prev = Some(token);
+    }
+
+    /// Ends the current line, keeping a single blank line if the source
+    /// had any between the two items.
+    fn break_line(&mut self, prev: &Token, next: &Token) {
+        self.out.push('\n');
+        if next.line_number > end_line(prev) + 1
+            && prev.token_type != LeftBrace
+            && next.token_type != RightBrace
+        {
+            self.out.push('\n');
+        }
+        self.out.push_str(&INDENT.repeat(self.indent));
+        self.newline = false;
+    }
+
+    fn spaced(&self, next: &Token) -> bool {
+        let Some(last) = self.last else {
+            return false;
+        };
+        if self.unary {
+            return false;
+        }
+        match next.token_type {
+            RightParen | Comma | Semicolon | Dot => false,
+            LeftParen => !matches!(last.token_type, Identifier | RightParen),
+            _ => !matches!(last.token_type, LeftParen | Dot),
+        }
+    }
+}
+
+/// Whether an operator following `last` would begin a new operand, which
+/// makes a `-` or `!` there a prefix operator rather than a binary one.
+fn starts_operand(last: Option<&Token>) -> bool {
+    !matches!(
+        last.map(|token| token.token_type),
+        Some(Identifier | StringLit | Number | RightParen | True | False | Nil | This | Super)
+    )
+}
+
+/// The line a token finishes on; string literals may span several.
+fn end_line(token: &Token) -> usize {
+    token.line_number + token.lexeme.matches('\n').count()
+}
+
+const USAGE: &str = "Usage: jlox fmt [--check] <path>...";
+
+/// `jlox fmt`: rewrites the given files, and the `.lox` files below the
+/// given directories, in place. With `--check` nothing is written; the
+/// files that would change are listed and the command fails instead.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let (check, paths) = match args.first().map(String::as_str) {
+        Some("--check") => (true, &args[1..]),
+        _ => (false, args),
+    };
+    if paths.is_empty() {
+        return Err(USAGE.to_string());
+    }
+
+    let mut files = vec![];
+    for path in paths {
+        collect_files(Path::new(path), &mut files)?;
+    }
+
+    let mut errors = vec![];
+    let mut unformatted = 0;
+    for file in &files {
+        let source = match fs::read_to_string(file) {
+            Ok(source) => source,
+            Err(err) => {
+                errors.push(format!("could not read '{}': {}", file.display(), err));
+                continue;
+            }
+        };
+        let formatted = match format_source(&source) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                errors.push(format!("{}:\n{}", file.display(), err));
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+
+        if check {
+            println!("{}", file.display());
+            unformatted += 1;
+        } else if let Err(err) = fs::write(file, formatted) {
+            errors.push(format!("could not write '{}': {}", file.display(), err));
+        }
+    }
+
+    if unformatted > 0 {
+        errors.push(format!("{} file(s) need formatting", unformatted));
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+pub(crate) fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let entries = fs::read_dir(path)
+        .map_err(|err| format!("could not read '{}': {}", path.display(), err))?;
+    let mut children: Vec<PathBuf> = entries.filter_map(|e| e.ok().map(|e| e.path())).collect();
+    children.sort();
+    for child in children {
+        if child.is_dir() || child.extension().is_some_and(|ext| ext == "lox") {
+            collect_files(&child, files)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_idempotent(source: &str) -> String {
+        let once = format_source(source).unwrap();
+        assert_eq!(format_source(&once).unwrap(), once);
+        once
+    }
+
+    #[test]
+    fn reprints_canonical_layout() {
+        let source = "var a=1;fun add(x,y){return x+y;}\n\
+                      if(a>=1){print add(a,-a);}else{print !true;}\n\
+                      for(var i=0;i<3;i=i+1)print i;";
+
+        assert_eq!(
+            assert_idempotent(source),
+            "var a = 1;\n\
+             fun add(x, y) {\n    return x + y;\n}\n\
+             if (a >= 1) {\n    print add(a, -a);\n} else {\n    print !true;\n}\n\
+             for (var i = 0; i < 3; i = i + 1) print i;\n"
+        );
+    }
+
+    #[test]
+    fn keeps_comments_and_single_blank_lines() {
+        let source = "// header\n\n\n\nvar a = 1;   // one\n\
+                      {  // open\n// inside\nprint a - -1;\n\n}\n\
+                      try { throw 1; }\ncatch (e) {} finally { print e; }\n";
+
+        assert_eq!(
+            assert_idempotent(source),
+            "// header\n\nvar a = 1; // one\n\
+             { // open\n    // inside\n    print a - -1;\n}\n\
+             try {\n    throw 1;\n} catch (e) {} finally {\n    print e;\n}\n"
+        );
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let samples = [
+            "",
+            "import \"lib.lox\" as lib;print lib.value . name;",
+            "while (true) { if (x) break; else continue; }",
+            "var s = \"multi\nline\";\n\n// trailing",
+            "fun f() {}\nf()(1)(2, 3);",
+            "print (1 + 2) * -(3 / 4) == !nil;",
+        ];
+        for sample in samples {
+            assert_idempotent(sample);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_syntax() {
+        assert!(format_source("var = 1;").is_err());
+        assert!(format_source("print \"open").is_err());
+    }
+}
@@ -0,0 +1,32 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::environment::Environment;
+use crate::scanner::Token;
+use crate::stmt::Stmt;
+
+/// A function declared in Lox code, together with the scope it was declared in.
+pub struct LoxFunction {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Rc<Vec<Stmt>>,
+    pub closure: Rc<RefCell<Environment>>,
+    /// The top level of the script or module the function was declared in,
+    /// where its resolved globals live.
+    pub globals: Rc<RefCell<Environment>>,
+    /// The file the function was declared in, for stack traces.
+    pub file: String,
+}
+
+impl fmt::Debug for LoxFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<fn {}>", self.name.lexeme)
+    }
+}
+
+impl PartialEq for LoxFunction {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
@@ -0,0 +1,304 @@
+//! Conversions between Lox values and Rust data through serde, for hosts
+//! that pass structured data in and out of scripts.
+//!
+//! Numbers, strings, booleans and nil map onto their serde counterparts.
+//! Sequences become lists, and maps and structs become maps whose fields
+//! scripts read as `value.field` or `get(value, "key")`. Namespaces, imported
+//! modules included, serialize into maps too, leaving out the functions they
+//! define. A list or map that contains itself can not be converted.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::de::{self, DeserializeOwned, MapAccess, SeqAccess, Visitor};
+use serde::ser::{self, SerializeMap, SerializeSeq};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::collection::{List, Map};
+use crate::expr::LiteralValue;
+
+thread_local! {
+    /// The lists and maps being serialized on this thread, outermost first.
+    static SERIALIZING: RefCell<Vec<*const ()>> = const { RefCell::new(vec![]) };
+}
+
+/// Converts any serializable Rust value into a Lox value.
+pub fn to_lox<T: Serialize + ?Sized>(value: &T) -> Result<LiteralValue, String> {
+    let json = serde_json::to_value(value).map_err(|err| err.to_string())?;
+    LiteralValue::deserialize(json).map_err(|err| err.to_string())
+}
+
+/// Converts a Lox value into any deserializable Rust value.
+pub fn from_lox<T: DeserializeOwned>(value: &LiteralValue) -> Result<T, String> {
+    let json = serde_json::to_value(value).map_err(|err| err.to_string())?;
+    T::deserialize(json).map_err(|err| err.to_string())
+}
+
+impl Serialize for LiteralValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            LiteralValue::Number(x) => serialize_number(*x, serializer),
+            LiteralValue::StringValue(s) => serializer.serialize_str(s),
+            LiteralValue::True => serializer.serialize_bool(true),
+            LiteralValue::False => serializer.serialize_bool(false),
+            LiteralValue::Nil => serializer.serialize_unit(),
+            LiteralValue::Namespace(module) => {
+                let members: BTreeMap<&String, &LiteralValue> = module
+                    .members
+                    .iter()
+                    .filter(|(_, value)| !is_function(value))
+                    .collect();
+                let mut map = serializer.serialize_map(Some(members.len()))?;
+                for (name, value) in members {
+                    map.serialize_entry(name, value)?;
+                }
+                map.end()
+            }
+            LiteralValue::List(list) => {
+                let _open = Open::enter(list.id())?;
+                let items = list.items();
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items.iter() {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            LiteralValue::Map(map) => {
+                let _open = Open::enter(map.id())?;
+                let entries = map.entries();
+                let mut out = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries.iter() {
+                    out.serialize_entry(key, value)?;
+                }
+                out.end()
+            }
+            LiteralValue::Error { message, line } => {
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry("message", message)?;
+                map.serialize_entry("line", line)?;
+                map.end()
+            }
+            LiteralValue::Callable(_) | LiteralValue::Function(_) => Err(ser::Error::custom(
+                format!("can not convert {} to a host value", self.to_type()),
+            )),
+        }
+    }
+}
+
+/// Marks a container as being serialized until dropped, failing if it
+/// already is, further out.
+struct Open(*const ());
+
+impl Open {
+    fn enter<E: ser::Error>(id: *const ()) -> Result<Self, E> {
+        SERIALIZING.with(|open| {
+            let mut open = open.borrow_mut();
+            if open.contains(&id) {
+                return Err(E::custom("can not convert a value that contains itself"));
+            }
+            open.push(id);
+            Ok(Self(id))
+        })
+    }
+}
+
+impl Drop for Open {
+    fn drop(&mut self) {
+        SERIALIZING.with(|open| open.borrow_mut().retain(|id| *id != self.0));
+    }
+}
+
+/// Numbers are `f32`s, so they are written the way Lox prints them rather
+/// than widened, and whole numbers as integers for integer fields.
+fn serialize_number<S: Serializer>(x: f32, serializer: S) -> Result<S::Ok, S::Error> {
+    if x.fract() == 0.0 && x.abs() < (1u64 << f32::MANTISSA_DIGITS) as f32 {
+        serializer.serialize_i64(x as i64)
+    } else {
+        let shortest = x.to_string().parse().unwrap_or(f64::from(x));
+        serializer.serialize_f64(shortest)
+    }
+}
+
+fn is_function(value: &LiteralValue) -> bool {
+    matches!(value, LiteralValue::Callable(_) | LiteralValue::Function(_))
+}
+
+impl<'de> Deserialize<'de> for LiteralValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(LiteralVisitor)
+    }
+}
+
+struct LiteralVisitor;
+
+impl<'de> Visitor<'de> for LiteralVisitor {
+    type Value = LiteralValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a number, string, boolean, nil, sequence or map")
+    }
+
+    fn visit_bool<E: de::Error>(self, b: bool) -> Result<LiteralValue, E> {
+        Ok(LiteralValue::from_bool(b))
+    }
+
+    fn visit_i64<E: de::Error>(self, x: i64) -> Result<LiteralValue, E> {
+        Ok(LiteralValue::Number(x as f32))
+    }
+
+    fn visit_u64<E: de::Error>(self, x: u64) -> Result<LiteralValue, E> {
+        Ok(LiteralValue::Number(x as f32))
+    }
+
+    fn visit_f64<E: de::Error>(self, x: f64) -> Result<LiteralValue, E> {
+        Ok(LiteralValue::Number(x as f32))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<LiteralValue, E> {
+        Ok(LiteralValue::StringValue(s.into()))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<LiteralValue, E> {
+        Ok(LiteralValue::Nil)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<LiteralValue, E> {
+        Ok(LiteralValue::Nil)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<LiteralValue, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<LiteralValue, A::Error> {
+        let mut items = vec![];
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+
+        Ok(LiteralValue::List(List::new(items)))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<LiteralValue, A::Error> {
+        let mut entries = BTreeMap::new();
+        while let Some((key, value)) = map.next_entry::<String, LiteralValue>()? {
+            entries.insert(key, value);
+        }
+
+        Ok(LiteralValue::Map(Map::new(entries)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::natives::Capabilities;
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        id: u32,
+        customer: String,
+        total: f64,
+        paid: bool,
+        note: Option<String>,
+        address: Address,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Address {
+        city: String,
+    }
+
+    #[test]
+    fn structs_round_trip_through_scripts() {
+        let order = Order {
+            id: 7,
+            customer: "Ada".to_string(),
+            total: 12.5,
+            paid: false,
+            note: None,
+            address: Address {
+                city: "London".to_string(),
+            },
+        };
+        let mut interpreter = Interpreter::with_capabilities(Capabilities { io: false }, vec![]);
+        interpreter.set_global("order", &order).unwrap();
+
+        crate::run(
+            &mut interpreter,
+            "var label = order.customer + \" in \" + order.address.city;\n\
+             var due = order.total * 2;\n\
+             var unpaid = !order.paid;\n\
+             var unnoted = order.note == nil;",
+        )
+        .unwrap();
+
+        assert_eq!(
+            interpreter.get_global::<String>("label"),
+            Ok("Ada in London".to_string())
+        );
+        assert_eq!(interpreter.get_global::<u32>("due"), Ok(25));
+        assert_eq!(interpreter.get_global::<bool>("unpaid"), Ok(true));
+        assert_eq!(interpreter.get_global::<bool>("unnoted"), Ok(true));
+        assert_eq!(interpreter.get_global::<Order>("order"), Ok(order));
+    }
+
+    #[test]
+    fn converts_json_values() {
+        let value = to_lox(&json!({"ratio": 0.1, "tags": null})).unwrap();
+        assert_eq!(
+            from_lox::<serde_json::Value>(&value),
+            Ok(json!({"ratio": 0.1, "tags": null}))
+        );
+
+        let list = to_lox(&json!([1, [2], {"content-type": "text"}])).unwrap();
+        assert_eq!(list.to_string(), r#"[1, [2], {"content-type": "text"}]"#);
+        assert_eq!(
+            from_lox::<serde_json::Value>(&list),
+            Ok(json!([1, [2], {"content-type": "text"}]))
+        );
+        assert_eq!(
+            from_lox::<serde_json::Value>(&LiteralValue::Error {
+                message: "Boom".to_string(),
+                line: 3
+            }),
+            Ok(json!({"message": "Boom", "line": 3}))
+        );
+    }
+
+    #[test]
+    fn containers_holding_themselves_are_rejected() {
+        let list = List::default();
+        list.items_mut().push(LiteralValue::List(list.clone()));
+
+        assert_eq!(
+            from_lox::<serde_json::Value>(&LiteralValue::List(list)),
+            Err("can not convert a value that contains itself".to_string())
+        );
+
+        let shared = to_lox(&json!({"a": 1})).unwrap();
+        let twice = LiteralValue::List(List::new(vec![shared.clone(), shared]));
+        assert_eq!(
+            from_lox::<serde_json::Value>(&twice),
+            Ok(json!([{"a": 1}, {"a": 1}]))
+        );
+    }
+
+    #[test]
+    fn functions_stay_in_lox() {
+        let mut interpreter = Interpreter::new();
+        crate::run(&mut interpreter, "fun f() {}").unwrap();
+
+        assert_eq!(
+            interpreter.get_global::<serde_json::Value>("f"),
+            Err("can not convert Function to a host value".to_string())
+        );
+        assert_eq!(
+            interpreter.get_global::<serde_json::Value>("missing"),
+            Err("Variable 'missing' has not been declared".to_string())
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+
+/// An interned string, compared and hashed by address instead of by its
+/// text. Identifiers, the names of natives and host globals, and the values
+/// of string literals are interned; other lexemes, and strings built while a
+/// script runs, are not.
+///
+/// Interned text lives for the rest of the process, so the table grows with
+/// the distinct names a process sees. A long `jlox lsp` session keeps every
+/// name and string constant typed in it, but not numbers, comments or any
+/// other text.
+#[derive(Clone, Copy)]
+pub struct Symbol(&'static &'static str);
+
+static INTERNER: Lazy<Mutex<HashMap<&'static str, Symbol>>> = Lazy::new(Mutex::default);
+
+thread_local! {
+    /// The symbols this thread has interned, checked before taking the lock
+    /// on the shared table.
+    static SEEN: RefCell<HashMap<&'static str, Symbol>> = RefCell::default();
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Self {
+        if let Some(symbol) = SEEN.with(|seen| seen.borrow().get(name).copied()) {
+            return symbol;
+        }
+
+        let symbol = {
+            let mut interner = INTERNER.lock().unwrap();
+            match interner.get(name) {
+                Some(symbol) => *symbol,
+                None => {
+                    let name: &'static str = Box::leak(name.into());
+                    let symbol = Symbol(Box::leak(Box::new(name)));
+                    interner.insert(name, symbol);
+                    symbol
+                }
+            }
+        };
+        SEEN.with(|seen| seen.borrow_mut().insert(symbol.as_str(), symbol));
+        symbol
+    }
+
+    pub fn as_str(self) -> &'static str {
+        self.0
+    }
+}
+
+impl Deref for Symbol {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.0
+    }
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_usize(self.0 as *const &str as usize);
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Self {
+        Symbol::intern(&name)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+/// Hashes a `Symbol` by mixing its address, which is much cheaper than
+/// running SipHash over it.
+#[derive(Default)]
+pub struct SymbolHasher(u64);
+
+impl Hasher for SymbolHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0.rotate_left(8) ^ u64::from(*byte)).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        }
+    }
+
+    fn write_usize(&mut self, address: usize) {
+        // Addresses are aligned, so their low bits carry nothing.
+        let mixed = (self.0 ^ (address as u64 >> 3)).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        self.0 = mixed ^ (mixed >> 32);
+    }
+}
+
+pub type SymbolMap<V> = HashMap<Symbol, V, BuildHasherDefault<SymbolHasher>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interns_each_name_once() {
+        let a = Symbol::intern("interner_test_name");
+        let b = Symbol::from("interner_test_name".to_string());
+
+        assert_eq!(a, b);
+        assert_ne!(a, Symbol::intern("interner_test_other"));
+        assert_eq!(a.as_str(), "interner_test_name");
+        assert_eq!(
+            format!("{} {:?}", a, a),
+            "interner_test_name \"interner_test_name\""
+        );
+    }
+}
@@ -1,37 +1,1183 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
 use crate::environment::Environment;
+use crate::exception::{Exception, Frame};
 use crate::expr::{Expr, LiteralValue};
+use crate::function::LoxFunction;
+use crate::host;
+use crate::interner::Symbol;
+use crate::limits::{self, LimitExceeded, Limits, Meter};
+use crate::module::{Module, ModuleCache};
+use crate::natives::{self, Capabilities};
+use crate::parser::Parser;
+use crate::resolver::{self, Location};
+use crate::scanner::{Scanner, Token};
 use crate::stmt::{Stmt, Stmt::*};
 
+/// Why a statement stopped before running to completion.
+enum Unwind {
+    Exception(Exception),
+    Return(LiteralValue),
+    Break,
+    Continue,
+}
+
+impl From<Exception> for Unwind {
+    fn from(exception: Exception) -> Self {
+        Unwind::Exception(exception)
+    }
+}
+
+/// Watches a script run, like the debugger does. Imported modules run in
+/// interpreters of their own and are not watched.
+pub trait Hook {
+    /// Called before every statement, with the interpreter paused on it.
+    fn before_statement(&mut self, interpreter: &mut Interpreter, stmt: &Stmt);
+
+    /// Called each time the condition of an `if` or `while` has been
+    /// tested, with whether its body is about to run.
+    fn after_condition(&mut self, _interpreter: &Interpreter, _stmt: &Stmt, _taken: bool) {}
+}
+
 pub struct Interpreter {
-    environment: Environment,
+    pub environment: Rc<RefCell<Environment>>,
+    /// The top-level scope, where resolved globals are read directly.
+    globals: Rc<RefCell<Environment>>,
+    capabilities: Capabilities,
+    args: Vec<String>,
+    file: Option<PathBuf>,
+    modules: Rc<RefCell<ModuleCache>>,
+    frames: Vec<Frame>,
+    hook: Option<Box<dyn Hook>>,
+    /// Where `print` writes; shared with the modules this script imports.
+    output: Rc<RefCell<dyn Write>>,
+    /// Shared with the modules this script imports, so they count against
+    /// the same limits.
+    meter: Rc<RefCell<Meter>>,
+    /// Set from another thread, or a signal handler, to stop the script.
+    interrupt: Arc<AtomicBool>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
-        Self {
-            environment: Environment::new(),
+        Self::with_capabilities(Capabilities::all(), vec![])
+    }
+
+    pub fn with_capabilities(capabilities: Capabilities, args: Vec<String>) -> Self {
+        Self::with_modules(capabilities, args, Rc::default())
+    }
+
+    fn with_modules(
+        capabilities: Capabilities,
+        args: Vec<String>,
+        modules: Rc<RefCell<ModuleCache>>,
+    ) -> Self {
+        let mut builtins = Environment::new();
+        natives::define_natives(&mut builtins, capabilities, args.clone());
+
+        let globals = Rc::new(RefCell::new(Environment::global(Rc::new(RefCell::new(
+            builtins,
+        )))));
+
+        let mut interpreter = Self {
+            environment: globals.clone(),
+            globals,
+            capabilities,
+            args,
+            file: None,
+            modules,
+            frames: vec![],
+            hook: None,
+            output: Rc::new(RefCell::new(io::stdout())),
+            meter: Rc::new(RefCell::new(Meter::new(Limits::default()))),
+            interrupt: Arc::default(),
+        };
+        interpreter.frames.push(Frame {
+            function: "<script>".to_string(),
+            file: interpreter.location(),
+            declared: 0,
+            line: 0,
+        });
+
+        interpreter
+    }
+
+    /// Sets the file being run, which relative imports are resolved against.
+    pub fn set_file(&mut self, path: &Path) {
+        self.file = Some(path.to_path_buf());
+        self.frames[0].file = self.location();
+    }
+
+    pub fn set_output(&mut self, output: Rc<RefCell<dyn Write>>) {
+        self.output = output;
+    }
+
+    /// Limits what scripts may do from now on. The step and time budgets
+    /// start afresh with each call.
+    pub fn set_limits(&mut self, limits: Limits) {
+        *self.meter.borrow_mut() = Meter::new(limits);
+    }
+
+    /// A flag that stops the running script when set. It is checked on
+    /// each pass through a loop and on each call, and cleared once the
+    /// script has stopped.
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        self.interrupt.clone()
+    }
+
+    /// Makes `interrupt` the flag that stops this interpreter, so one
+    /// handler can serve interpreters created later.
+    pub fn set_interrupt(&mut self, interrupt: Arc<AtomicBool>) {
+        self.interrupt = interrupt;
+    }
+
+    pub fn set_hook(&mut self, hook: Box<dyn Hook>) {
+        self.hook = Some(hook);
+    }
+
+    /// Defines a global holding `value`, converted to a Lox value.
+    pub fn set_global<T: Serialize + ?Sized>(
+        &mut self,
+        name: &str,
+        value: &T,
+    ) -> Result<(), String> {
+        let value = host::to_lox(value)?;
+        self.environment
+            .borrow_mut()
+            .define(name.to_string(), value);
+        Ok(())
+    }
+
+    /// Reads the variable `name`, converted to a Rust value.
+    pub fn get_global<T: DeserializeOwned>(&self, name: &str) -> Result<T, String> {
+        match self.environment.borrow().get(name) {
+            Some(value) => host::from_lox(&value),
+            None => Err(format!("Variable '{}' has not been declared", name)),
         }
     }
 
-    pub fn interpret(&mut self, stmts: Vec<Stmt>) -> Result<(), String> {
+    /// The calls in progress, outermost (the script itself) first.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn interpret(&mut self, mut stmts: Vec<Stmt>) -> Result<(), Exception> {
+        self.resolve(&mut stmts);
+        let result = self.execute_top_level(&stmts);
+        self.clear_interrupt();
+        result
+    }
+
+    /// Runs a script the way `interpret` does, `test` blocks skipped, and
+    /// then the body of one of its tests. The script is shared between
+    /// tests, so it is up to the caller to resolve it once.
+    pub fn interpret_test(&mut self, stmts: &[Stmt], test: &[Stmt]) -> Result<(), Exception> {
+        let result = self.execute_top_level(stmts).and_then(|()| {
+            let result = self.execute_block(test);
+            self.top_level(result)
+        });
+        self.clear_interrupt();
+        result
+    }
+
+    fn execute_top_level(&mut self, stmts: &[Stmt]) -> Result<(), Exception> {
         for stmt in stmts {
-            match stmt {
-                Expression { expression } => {
-                    let _ = expression.evaluate(&mut self.environment)?;
+            let result = self.execute(stmt);
+            self.top_level(result)?;
+        }
+
+        Ok(())
+    }
+
+    fn top_level(&mut self, result: Result<(), Unwind>) -> Result<(), Exception> {
+        match result {
+            Ok(()) => Ok(()),
+            Err(Unwind::Exception(exception)) => Err(self.traced(exception)),
+            Err(Unwind::Return(_) | Unwind::Break | Unwind::Continue) => {
+                unreachable!("the parser rejects these outside functions and loops")
+            }
+        }
+    }
+
+    /// Runs `stmts` like `interpret`, but hands back the value of a final
+    /// expression statement for the REPL to echo.
+    pub fn interpret_repl(
+        &mut self,
+        mut stmts: Vec<Stmt>,
+    ) -> Result<Option<LiteralValue>, Exception> {
+        self.resolve(&mut stmts);
+        let last = match stmts.last() {
+            Some(Expression { .. }) => stmts.pop(),
+            _ => None,
+        };
+        let result = self.execute_top_level(&stmts).and_then(|()| match last {
+            Some(Expression { expression }) => match expression.evaluate(self) {
+                Ok(value) => Ok(Some(value)),
+                Err(exception) => Err(self.traced(exception)),
+            },
+            _ => Ok(None),
+        });
+        self.clear_interrupt();
+        result
+    }
+
+    /// Whether code runs in the global scope, rather than in a function or
+    /// block, as it does while the debugger is paused in one.
+    pub(crate) fn at_top_level(&self) -> bool {
+        Rc::ptr_eq(&self.environment, &self.globals)
+    }
+
+    /// Resolves statements about to run at the top level. Ones run from
+    /// inside a scope, as the debugger does while paused, are left to look
+    /// their variables up by name.
+    fn resolve(&self, stmts: &mut [Stmt]) {
+        if self.at_top_level() {
+            resolver::resolve(stmts);
+        }
+    }
+
+    /// Reads the variable `name` from where the resolver found it.
+    pub(crate) fn lookup(&self, name: Symbol, location: Location) -> Option<LiteralValue> {
+        match location {
+            Location::Local { depth, slot } => self.environment.borrow().get_at(depth, slot),
+            Location::Global => self.globals.borrow().get(name),
+            Location::Unresolved => self.environment.borrow().get(name),
+        }
+    }
+
+    pub(crate) fn assign(&mut self, name: Symbol, location: Location, value: LiteralValue) -> bool {
+        match location {
+            Location::Local { depth, slot } => {
+                self.environment.borrow_mut().assign_at(depth, slot, value)
+            }
+            Location::Global => self.globals.borrow_mut().assign(name, value),
+            Location::Unresolved => self.environment.borrow_mut().assign(name, value),
+        }
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> Result<(), Unwind> {
+        let step = self.meter.borrow_mut().step();
+        step.map_err(|exceeded| Exception::limit(exceeded, stmt.line().unwrap_or_default()))?;
+
+        // Taken out while it runs, so whatever the hook evaluates is not
+        // watched itself.
+        if let Some(mut hook) = self.hook.take() {
+            hook.before_statement(self, stmt);
+            self.hook = Some(hook);
+        }
+
+        match stmt {
+            Block { statements } => self.execute_block(statements)?,
+            Break { .. } => return Err(Unwind::Break),
+            Continue { .. } => return Err(Unwind::Continue),
+            Expression { expression } => {
+                let _ = expression.evaluate(self)?;
+            }
+            Function { name, params, body } => {
+                let function = LoxFunction {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: body.clone(),
+                    closure: self.environment.clone(),
+                    globals: self.globals.clone(),
+                    file: self.location(),
+                };
+
+                self.environment.borrow_mut().define(
+                    name.lexeme.symbol(),
+                    LiteralValue::Function(Rc::new(function)),
+                )
+            }
+            If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                if self.test(stmt, condition)? {
+                    self.execute(then_branch)?
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)?
                 }
-                Print { expression } => {
-                    let value = expression.evaluate(&mut self.environment)?;
+            }
+            Import { path, name } => {
+                let module = self.import(path)?;
+
+                self.environment
+                    .borrow_mut()
+                    .define(name.lexeme.symbol(), module)
+            }
+            Print { expression } => {
+                let value = expression.evaluate(self)?;
 
-                    println!("{value:?}")
+                writeln!(self.output.borrow_mut(), "{value}").map_err(|err| {
+                    Exception::error(format!("Could not print: {}", err), expression.line())
+                })?
+            }
+            Return { value, .. } => {
+                let value = value.evaluate(self)?;
+
+                return Err(Unwind::Return(value));
+            }
+            // Only `jlox test` runs these, through `interpret_test`.
+            Test { .. } => (),
+            Throw { keyword, value } => {
+                let value = value.evaluate(self)?;
+
+                return Err(Exception::throw(value, keyword.line_number).into());
+            }
+            Try {
+                body,
+                catch,
+                finally,
+            } => {
+                let mut result = self.execute_block(body);
+
+                match (&result, catch) {
+                    // Limits and `exit` stop the whole script, whatever it
+                    // tries to catch.
+                    (Err(Unwind::Exception(exception)), Some((name, handler)))
+                        if exception.catchable() =>
+                    {
+                        let mut scope = Environment::with_enclosing(self.environment.clone());
+                        scope.define(name.lexeme.symbol(), exception.value.clone());
+
+                        result = self.execute_with(handler, scope);
+                    }
+                    _ => (),
                 }
-                Var { name, initializer } => {
-                    let value = initializer.evaluate(&mut self.environment)?;
 
-                    self.environment.define(name.lexeme, value)
+                if let Some(finally) = finally {
+                    let finished = self.execute_block(finally);
+                    // A `break`, `continue` or `return` in `finally` can not
+                    // cancel a limit or `exit` on its way out.
+                    match &result {
+                        Err(Unwind::Exception(exception)) if !exception.catchable() => (),
+                        _ => finished?,
+                    }
+                }
+
+                result?
+            }
+            Var { name, initializer } => {
+                let value = initializer.evaluate(self)?;
+
+                self.environment
+                    .borrow_mut()
+                    .define(name.lexeme.symbol(), value)
+            }
+            While {
+                condition,
+                body,
+                increment,
+            } => {
+                while self.test(stmt, condition)? {
+                    self.check_interrupt(condition.line())?;
+                    match self.execute(body) {
+                        Ok(()) | Err(Unwind::Continue) => (),
+                        Err(Unwind::Break) => break,
+                        Err(unwind) => return Err(unwind),
+                    }
+
+                    if let Some(increment) = increment {
+                        increment.evaluate(self)?;
+                    }
                 }
             }
         }
 
         Ok(())
     }
+
+    /// Evaluates the condition of `stmt`, letting the hook know which way
+    /// it went.
+    fn test(&mut self, stmt: &Stmt, condition: &Expr) -> Result<bool, Exception> {
+        let taken = condition.evaluate(self)?.is_truthy();
+        if let Some(mut hook) = self.hook.take() {
+            hook.after_condition(self, stmt, taken);
+            self.hook = Some(hook);
+        }
+        Ok(taken)
+    }
+
+    /// Runs a block in a scope of its own, unless it declares nothing.
+    fn execute_block(&mut self, statements: &[Stmt]) -> Result<(), Unwind> {
+        if !resolver::declares(statements) {
+            for stmt in statements {
+                self.execute(stmt)?;
+            }
+            return Ok(());
+        }
+
+        let scope = Environment::with_enclosing(self.environment.clone());
+        self.execute_with(statements, scope)
+    }
+
+    fn execute_with(&mut self, statements: &[Stmt], scope: Environment) -> Result<(), Unwind> {
+        let previous = mem::replace(&mut self.environment, Rc::new(RefCell::new(scope)));
+
+        let mut result = Ok(());
+        for stmt in statements {
+            result = self.execute(stmt);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        self.environment = previous;
+        result
+    }
+
+    pub fn call_function(
+        &mut self,
+        function: &LoxFunction,
+        arguments: Vec<LiteralValue>,
+        line: usize,
+    ) -> Result<LiteralValue, Exception> {
+        self.check_interrupt(line)?;
+        let enter = self.meter.borrow_mut().enter();
+        enter.map_err(|exceeded| Exception::limit(exceeded, line))?;
+
+        let mut scope = Environment::with_enclosing(function.closure.clone());
+        for (param, argument) in function.params.iter().zip(arguments) {
+            scope.define(param.lexeme.symbol(), argument);
+        }
+
+        if let Some(caller) = self.frames.last_mut() {
+            caller.line = line;
+        }
+        self.frames.push(Frame {
+            function: function.name.lexeme.to_string(),
+            file: function.file.clone(),
+            declared: function.name.line_number,
+            line: function.name.line_number,
+        });
+
+        // A function imported from a module reads that module's globals.
+        let globals = mem::replace(&mut self.globals, function.globals.clone());
+        let result = limits::with_stack(|| self.execute_with(&function.body, scope));
+        self.globals = globals;
+
+        let result = match result {
+            Ok(()) => Ok(LiteralValue::Nil),
+            Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Exception(exception)) => Err(self.traced(exception)),
+            Err(Unwind::Break | Unwind::Continue) => {
+                unreachable!("the parser rejects loop control outside loops")
+            }
+        };
+
+        self.frames.pop();
+        self.meter.borrow_mut().leave();
+        result
+    }
+
+    fn check_interrupt(&self, line: usize) -> Result<(), Exception> {
+        match self.interrupt.load(Ordering::Relaxed) {
+            true => Err(Exception::limit(LimitExceeded::Interrupted, line)),
+            false => Ok(()),
+        }
+    }
+
+    /// An interrupt stays set while the script unwinds, so that no loop in a
+    /// `finally` block on the way out can outlast it, and is cleared once
+    /// the outermost script has returned.
+    fn clear_interrupt(&self) {
+        if self.modules.borrow().loading.is_empty() {
+            self.interrupt.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Fails if a string of `length` bytes would be over the limit.
+    pub(crate) fn check_string(&self, length: usize, line: usize) -> Result<(), Exception> {
+        self.meter
+            .borrow()
+            .check_string(length)
+            .map_err(|exceeded| Exception::limit(exceeded, line))
+    }
+
+    /// Fails if `value` is a list or map with more entries than the limit.
+    pub(crate) fn check_collection(
+        &self,
+        value: &LiteralValue,
+        line: usize,
+    ) -> Result<(), Exception> {
+        let length = match value {
+            LiteralValue::List(list) => list.items().len(),
+            LiteralValue::Map(map) => map.entries().len(),
+            _ => return Ok(()),
+        };
+        self.meter
+            .borrow()
+            .check_collection(length)
+            .map_err(|exceeded| Exception::limit(exceeded, line))
+    }
+
+    /// Records the call stack on an exception the first time it leaves a
+    /// function, while the frames it passed through are still in place.
+    fn traced(&self, mut exception: Exception) -> Exception {
+        if exception.trace.is_empty() {
+            exception.trace = self.traceback(exception.line);
+        }
+
+        exception
+    }
+
+    /// The current call stack, innermost first, with the innermost frame
+    /// positioned at `line`.
+    fn traceback(&self, line: usize) -> Vec<Frame> {
+        let mut frames: Vec<Frame> = self.frames.iter().rev().cloned().collect();
+        if let Some(innermost) = frames.first_mut() {
+            innermost.line = line;
+        }
+
+        frames
+    }
+
+    fn location(&self) -> String {
+        match &self.file {
+            Some(file) => file.display().to_string(),
+            None => "<script>".to_string(),
+        }
+    }
+
+    fn import(&mut self, path: &Token) -> Result<LiteralValue, Exception> {
+        let relative = path.lexeme.trim_matches('"');
+        let error = |msg: String| Exception::error(msg, path.line_number);
+        if !self.capabilities.io {
+            return Err(error(format!(
+                "Could not import '{}': imports need the io capability",
+                relative
+            )));
+        }
+
+        let base = match &self.file {
+            Some(file) => file.parent().map(Path::to_path_buf).unwrap_or_default(),
+            None => PathBuf::new(),
+        };
+        let canonical = fs::canonicalize(base.join(relative))
+            .map_err(|err| error(format!("Could not import '{}': {}", relative, err)))?;
+
+        if let Some(module) = self.modules.borrow().loaded.get(&canonical) {
+            return Ok(LiteralValue::Namespace(module.clone()));
+        }
+        if let Some(msg) = self.modules.borrow().cycle_error(&canonical) {
+            return Err(error(msg));
+        }
+
+        let source = fs::read_to_string(&canonical)
+            .map_err(|err| error(format!("Could not import '{}': {}", relative, err)))?;
+        let stmts = Scanner::new(&source)
+            .scan_tokens()
+            .and_then(|tokens| Parser::new(tokens).parse())
+            .map_err(|msg| error(format!("Could not parse '{}':\n{}", relative, msg)))?;
+
+        let mut interpreter =
+            Interpreter::with_modules(self.capabilities, self.args.clone(), self.modules.clone());
+        interpreter.set_file(&canonical);
+        interpreter.output = self.output.clone();
+        interpreter.meter = self.meter.clone();
+        interpreter.interrupt = self.interrupt.clone();
+        interpreter.frames[0].function = "<module>".to_string();
+
+        self.modules.borrow_mut().loading.push(canonical.clone());
+        let result = interpreter.interpret(stmts);
+        self.modules.borrow_mut().loading.pop();
+        // The module traced its own frames; continue the trace from the import.
+        result.map_err(|mut exception| {
+            exception.trace.extend(self.traceback(path.line_number));
+            exception
+        })?;
+
+        let module = Rc::new(Module {
+            path: canonical.clone(),
+            members: interpreter
+                .environment
+                .borrow()
+                .values()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+        });
+        self.modules
+            .borrow_mut()
+            .loaded
+            .insert(canonical, module.clone());
+
+        Ok(LiteralValue::Namespace(module))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
+        let tokens = Scanner::new(source).scan_tokens()?;
+        let stmts = Parser::new(tokens).parse()?;
+        interpreter
+            .interpret(stmts)
+            .map_err(|exception| exception.message())
+    }
+
+    fn module_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&dir);
+        for (file, contents) in files {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn io_natives_round_trip_a_file() {
+        let path = std::env::temp_dir().join("jlox_io_natives_round_trip.txt");
+        let path = path.to_str().unwrap();
+        let mut interpreter = Interpreter::new();
+
+        let source = format!(
+            "writeFile(\"{path}\", \"one\"); appendFile(\"{path}\", 2); var contents = readFile(\"{path}\");"
+        );
+        run(&mut interpreter, &source).unwrap();
+
+        assert_eq!(
+            interpreter.environment.borrow().get("contents"),
+            Some(LiteralValue::StringValue("one2".into()))
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn script_args_are_exposed() {
+        let args = vec!["first".to_string(), "second".to_string()];
+        let mut interpreter = Interpreter::with_capabilities(Capabilities::all(), args);
+
+        run(
+            &mut interpreter,
+            "var n = argCount(); var a = arg(1); var b = arg(2);",
+        )
+        .unwrap();
+
+        assert_eq!(
+            interpreter.environment.borrow().get("n"),
+            Some(LiteralValue::Number(2.0))
+        );
+        assert_eq!(
+            interpreter.environment.borrow().get("a"),
+            Some(LiteralValue::StringValue("second".into()))
+        );
+        assert_eq!(
+            interpreter.environment.borrow().get("b"),
+            Some(LiteralValue::Nil)
+        );
+    }
+
+    #[test]
+    fn io_natives_are_absent_without_capability() {
+        let mut interpreter = Interpreter::with_capabilities(Capabilities { io: false }, vec![]);
+
+        let result = run(&mut interpreter, "readFile(\"/etc/passwd\");");
+
+        assert_eq!(
+            result,
+            Err("Variable 'readFile' has not been declared".to_string())
+        );
+        assert_eq!(
+            run(&mut interpreter, "import \"/etc/passwd\" as secrets;"),
+            Err("Could not import '/etc/passwd': imports need the io capability".to_string())
+        );
+    }
+
+    #[test]
+    fn assert_fails_with_its_message() {
+        let mut interpreter = Interpreter::with_capabilities(Capabilities { io: false }, vec![]);
+
+        assert_eq!(run(&mut interpreter, "assert(1 < 2, \"fine\");"), Ok(()));
+        assert_eq!(
+            run(&mut interpreter, "assert(nil, \"expected a value\");"),
+            Err("Assertion failed: expected a value".to_string())
+        );
+    }
+
+    #[test]
+    fn native_arity_is_checked() {
+        let mut interpreter = Interpreter::new();
+
+        let result = run(&mut interpreter, "getEnv();");
+
+        assert_eq!(
+            result,
+            Err("getEnv expected 1 arguments but got 0".to_string())
+        );
+    }
+
+    #[test]
+    fn imports_resolve_relative_to_the_importing_file() {
+        let dir = module_dir(
+            "jlox_imports_relative",
+            &[
+                ("main.lox", "import \"lib/outer.lox\" as outer;"),
+                (
+                    "lib/outer.lox",
+                    "import \"inner.lox\" as inner; var name = inner.name + \"!\";",
+                ),
+                ("lib/inner.lox", "var name = \"inner\";"),
+            ],
+        );
+        let main = dir.join("main.lox");
+        let mut interpreter = Interpreter::new();
+        interpreter.set_file(&main);
+
+        run(&mut interpreter, &fs::read_to_string(&main).unwrap()).unwrap();
+        run(&mut interpreter, "var name = outer.name;").unwrap();
+
+        assert_eq!(
+            interpreter.environment.borrow().get("name"),
+            Some(LiteralValue::StringValue("inner!".into()))
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn modules_are_executed_once() {
+        let log = std::env::temp_dir().join("jlox_imports_cached.log");
+        let counter = format!("appendFile(\"{}\", \"x\");", log.display());
+        let dir = module_dir("jlox_imports_cached", &[("counter.lox", &counter)]);
+        let _ = fs::remove_file(&log);
+        let mut interpreter = Interpreter::new();
+        interpreter.set_file(&dir.join("main.lox"));
+
+        let source =
+            "import \"counter.lox\" as a; import \"./counter.lox\" as b; var same = a == b;";
+        run(&mut interpreter, source).unwrap();
+
+        assert_eq!(
+            interpreter.environment.borrow().get("same"),
+            Some(LiteralValue::True)
+        );
+        assert_eq!(fs::read_to_string(&log).unwrap(), "x");
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_file(log).unwrap();
+    }
+
+    #[test]
+    fn import_cycles_are_reported() {
+        let dir = module_dir(
+            "jlox_imports_cycle",
+            &[
+                ("a.lox", "import \"b.lox\" as b;"),
+                ("b.lox", "import \"a.lox\" as a;"),
+            ],
+        );
+        let mut interpreter = Interpreter::new();
+        interpreter.set_file(&dir.join("main.lox"));
+
+        let err = run(&mut interpreter, "import \"a.lox\" as a;").unwrap_err();
+
+        let a = fs::canonicalize(dir.join("a.lox")).unwrap();
+        let b = fs::canonicalize(dir.join("b.lox")).unwrap();
+        assert!(err.contains(&format!(
+            "Import cycle detected: {} -> {} -> {}",
+            a.display(),
+            b.display(),
+            a.display()
+        )));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn catch_receives_thrown_values() {
+        let mut interpreter = Interpreter::new();
+
+        run(
+            &mut interpreter,
+            "var caught; try { throw \"oops\"; caught = \"no\"; } catch (e) { caught = e; }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            interpreter.environment.borrow().get("caught"),
+            Some(LiteralValue::StringValue("oops".into()))
+        );
+    }
+
+    #[test]
+    fn runtime_errors_are_catchable_error_objects() {
+        let mut interpreter = Interpreter::new();
+
+        run(
+            &mut interpreter,
+            "var message; var line;\ntry {\n  1 / 0;\n} catch (e) { message = e.message; line = e.line; }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            interpreter.environment.borrow().get("message"),
+            Some(LiteralValue::StringValue("Division by zero".into()))
+        );
+        assert_eq!(
+            interpreter.environment.borrow().get("line"),
+            Some(LiteralValue::Number(3.0))
+        );
+    }
+
+    #[test]
+    fn finally_runs_and_uncaught_exceptions_propagate() {
+        let mut interpreter = Interpreter::new();
+
+        let result = run(
+            &mut interpreter,
+            "var cleaned = false; try { { var inner = 1; throw inner; } } finally { cleaned = true; }",
+        );
+
+        assert_eq!(result, Err("1".to_string()));
+        assert_eq!(
+            interpreter.environment.borrow().get("cleaned"),
+            Some(LiteralValue::True)
+        );
+        assert_eq!(interpreter.environment.borrow().get("inner"), None);
+    }
+
+    #[test]
+    fn uncaught_exceptions_print_a_trace() {
+        let dir = module_dir("jlox_uncaught_trace", &[("lib.lox", "\nthrow \"bad\";")]);
+        let main = dir.join("main.lox");
+        let mut interpreter = Interpreter::new();
+        interpreter.set_file(&main);
+
+        let tokens = Scanner::new("import \"lib.lox\" as lib;")
+            .scan_tokens()
+            .unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        let exception = interpreter.interpret(stmts).unwrap_err();
+
+        let lib = fs::canonicalize(dir.join("lib.lox")).unwrap();
+        assert_eq!(
+            exception.to_string(),
+            format!(
+                "Uncaught exception: bad\n  at <module> ({}:2)\n  at <script> ({}:1)",
+                lib.display(),
+                main.display()
+            )
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn functions_return_values_and_close_over_scopes() {
+        let mut interpreter = Interpreter::new();
+
+        run(
+            &mut interpreter,
+            "fun counter() { var n = 0; fun next() { n = n + 1; return n; } return next; }\nvar next = counter(); next(); var second = next();",
+        )
+        .unwrap();
+
+        assert_eq!(
+            interpreter.environment.borrow().get("second"),
+            Some(LiteralValue::Number(2.0))
+        );
+    }
+
+    #[test]
+    fn uncaught_errors_trace_the_call_stack() {
+        let mut interpreter = Interpreter::new();
+        let tokens =
+            Scanner::new("fun inner() {\n  return 1 / 0;\n}\nfun outer() { inner(); }\nouter();")
+                .scan_tokens()
+                .unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+
+        let exception = interpreter.interpret(stmts).unwrap_err();
+
+        assert_eq!(
+            exception.to_string(),
+            "Uncaught Error: Division by zero\n  at inner (<script>:2)\n  at outer (<script>:4)\n  at <script> (<script>:5)"
+        );
+        assert_eq!(interpreter.frames.len(), 1);
+    }
+
+    #[test]
+    fn runaway_recursion_is_a_stack_overflow() {
+        let result = std::thread::spawn(|| run(&mut Interpreter::new(), "fun f() { f(); } f();"))
+            .join()
+            .unwrap();
+
+        assert_eq!(result, Err("Stack overflow".to_string()));
+    }
+
+    #[test]
+    fn deep_recursion_fits_a_default_thread() {
+        let result = std::thread::spawn(|| -> Result<String, String> {
+            let mut interpreter = Interpreter::new();
+            let source = "fun depth(n) { if (n == 0) return 0; return depth(n - 1) + 1; }
+                          var reached = depth(999);";
+            run(&mut interpreter, source)?;
+            let reached = interpreter.environment.borrow().get("reached");
+            Ok(reached.unwrap().to_string())
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(result, Ok("999".to_string()));
+    }
+
+    #[test]
+    fn long_operator_chains_fit_a_default_thread() {
+        let result = std::thread::spawn(|| {
+            let source = format!("var total = {}1;", "1 + ".repeat(5000));
+            let mut interpreter = Interpreter::new();
+            run(&mut interpreter, &source)?;
+            let total = interpreter.environment.borrow().get("total");
+            Ok::<_, String>(total.unwrap().to_string())
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(result, Ok("5001".to_string()));
+    }
+
+    /// Runs `source` under `limits`, returning the limit it ran into.
+    fn exceeded(limits: Limits, source: &str) -> (Option<LimitExceeded>, Interpreter) {
+        let mut interpreter = Interpreter::with_capabilities(Capabilities { io: false }, vec![]);
+        interpreter.set_limits(limits);
+        let stmts = Parser::new(Scanner::new(source).scan_tokens().unwrap())
+            .parse()
+            .unwrap();
+
+        let limit = interpreter.interpret(stmts).err().and_then(|err| err.limit);
+        (limit, interpreter)
+    }
+
+    #[test]
+    fn step_limit_cannot_be_caught() {
+        let limits = Limits {
+            max_steps: Some(1000),
+            ..Limits::default()
+        };
+
+        let (limit, interpreter) = exceeded(
+            limits,
+            "var caught = false;\ntry { while (true) {} } catch (e) { caught = true; }",
+        );
+
+        assert_eq!(limit, Some(LimitExceeded::Steps(1000)));
+        assert_eq!(
+            interpreter.environment.borrow().get("caught"),
+            Some(LiteralValue::False)
+        );
+    }
+
+    #[test]
+    fn timeout_stops_infinite_loops() {
+        let timeout = std::time::Duration::from_millis(50);
+        let limits = Limits {
+            timeout: Some(timeout),
+            ..Limits::default()
+        };
+
+        let (limit, _) = exceeded(limits, "while (true) {}");
+
+        assert_eq!(limit, Some(LimitExceeded::Timeout(timeout)));
+    }
+
+    #[test]
+    fn strings_cannot_outgrow_the_limit() {
+        let limits = Limits {
+            max_string_length: Some(1024),
+            ..Limits::default()
+        };
+
+        let (limit, interpreter) = exceeded(limits, "var s = \"ab\"; while (true) s = s + s;");
+
+        assert_eq!(limit, Some(LimitExceeded::StringLength(1024)));
+        assert_eq!(
+            interpreter.environment.borrow().get("s"),
+            Some(LiteralValue::StringValue("ab".repeat(512).into()))
+        );
+    }
+
+    #[test]
+    fn collections_cannot_outgrow_the_limit() {
+        let limits = Limits {
+            max_collection_len: Some(100),
+            ..Limits::default()
+        };
+
+        let (limit, _) = exceeded(limits, "var l = list(); while (true) push(l, l);");
+
+        assert_eq!(limit, Some(LimitExceeded::CollectionLength(100)));
+
+        let (limit, interpreter) = exceeded(
+            limits,
+            "var m = map(); var key = \"k\"; var i = 0;\n\
+             while (true) { set(m, key, i); key = key + \"k\"; i = i + 1; }",
+        );
+        assert_eq!(limit, Some(LimitExceeded::CollectionLength(100)));
+        assert_eq!(
+            interpreter.environment.borrow().get("i"),
+            Some(LiteralValue::Number(100.0))
+        );
+    }
+
+    #[test]
+    fn call_depth_is_configurable() {
+        let limits = Limits {
+            max_call_depth: 10,
+            ..Limits::default()
+        };
+        let mut interpreter = Interpreter::new();
+        interpreter.set_limits(limits);
+
+        let err = run(
+            &mut interpreter,
+            "fun down(n) { if (n > 0) return down(n - 1); return 0; }\ndown(10);",
+        );
+        assert_eq!(err, Err("Stack overflow".to_string()));
+        assert_eq!(run(&mut interpreter, "down(9);"), Ok(()));
+    }
+
+    #[test]
+    fn exit_unwinds_through_finally_but_not_catch() {
+        let mut interpreter = Interpreter::new();
+        let source = "var seen = \"\";\n\
+                      try { exit(3); } catch (e) { seen = \"catch\"; } finally { seen = \"finally\"; }\n\
+                      seen = \"after\";";
+        let stmts = Parser::new(Scanner::new(source).scan_tokens().unwrap())
+            .parse()
+            .unwrap();
+
+        let err = interpreter.interpret(stmts).unwrap_err();
+
+        assert_eq!(err.exit, Some(3));
+        assert_eq!(err.line, 2);
+        assert_eq!(
+            interpreter.environment.borrow().get("seen"),
+            Some(LiteralValue::StringValue("finally".into()))
+        );
+    }
+
+    #[test]
+    fn interrupts_stop_the_script_once() {
+        let mut interpreter = Interpreter::new();
+        let interrupt = interpreter.interrupt_handle();
+        let setter = std::thread::spawn({
+            let interrupt = interrupt.clone();
+            move || {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                interrupt.store(true, Ordering::Relaxed);
+            }
+        });
+
+        let source = "var caught = false;\ntry { while (true) {} } catch (e) { caught = true; }";
+        let stmts = Parser::new(Scanner::new(source).scan_tokens().unwrap())
+            .parse()
+            .unwrap();
+        let err = interpreter.interpret(stmts).unwrap_err();
+        setter.join().unwrap();
+
+        assert_eq!(err.limit, Some(LimitExceeded::Interrupted));
+        assert!(err.to_string().starts_with("Interrupted\n"));
+        assert!(!interrupt.load(Ordering::Relaxed));
+        assert_eq!(run(&mut interpreter, "caught = 1;"), Ok(()));
+    }
+
+    #[test]
+    fn finally_can_not_swallow_an_interrupt() {
+        let mut interpreter = Interpreter::new();
+        let interrupt = interpreter.interrupt_handle();
+        let setter = std::thread::spawn({
+            let interrupt = interrupt.clone();
+            move || {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                interrupt.store(true, Ordering::Relaxed);
+            }
+        });
+
+        let source = "while (true) { try { while (true) {} } finally { continue; } }";
+        let stmts = Parser::new(Scanner::new(source).scan_tokens().unwrap())
+            .parse()
+            .unwrap();
+        let err = interpreter.interpret(stmts).unwrap_err();
+        setter.join().unwrap();
+
+        assert_eq!(err.limit, Some(LimitExceeded::Interrupted));
+        assert!(!interrupt.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn break_and_continue_unwind_nested_blocks() {
+        let mut interpreter = Interpreter::new();
+
+        run(
+            &mut interpreter,
+            "var seen = \"\"; var i = 0;
+             while (true) {
+               i = i + 1;
+               { var inner = i; if (inner == 2) { continue; } if (inner == 4) { { break; } } }
+               seen = seen + \"x\";
+             }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            interpreter.environment.borrow().get("seen"),
+            Some(LiteralValue::StringValue("xx".into()))
+        );
+        assert_eq!(interpreter.environment.borrow().get("inner"), None);
+    }
+
+    #[test]
+    fn continue_runs_the_for_increment() {
+        let mut interpreter = Interpreter::new();
+
+        run(
+            &mut interpreter,
+            "var total = 0; for (var i = 0; i < 5; i = i + 1) { if (i == 1) continue; total = total + i; }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            interpreter.environment.borrow().get("total"),
+            Some(LiteralValue::Number(9.0))
+        );
+        assert_eq!(interpreter.environment.borrow().get("i"), None);
+    }
+
+    #[test]
+    fn repl_returns_the_final_expression_value() {
+        let mut interpreter = Interpreter::new();
+        let parse = |source: &str| {
+            Parser::for_repl(Scanner::new(source).scan_tokens().unwrap())
+                .parse()
+                .unwrap()
+        };
+
+        assert_eq!(
+            interpreter.interpret_repl(parse("var a = 1; a + 2")),
+            Ok(Some(LiteralValue::Number(3.0)))
+        );
+        assert_eq!(
+            interpreter.interpret_repl(parse("a = 5;")),
+            Ok(Some(LiteralValue::Number(5.0)))
+        );
+        assert_eq!(interpreter.interpret_repl(parse("print a;")), Ok(None));
+    }
 }
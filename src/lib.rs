@@ -0,0 +1,69 @@
+//! A tree-walking interpreter for Lox, and the tools built around it.
+
+pub mod ast_json;
+pub mod collection;
+pub mod coverage;
+pub mod dap;
+pub mod debugger;
+pub mod environment;
+pub mod exception;
+pub mod expr;
+pub mod formatter;
+pub mod function;
+pub mod host;
+pub mod interner;
+pub mod interpreter;
+pub mod limits;
+pub mod lint;
+pub mod lsp;
+pub mod module;
+pub mod natives;
+pub mod optimizer;
+pub mod parser;
+pub mod profiler;
+pub mod repl;
+pub mod resolver;
+pub mod scanner;
+pub mod stmt;
+pub mod testing;
+
+use crate::exception::Exception;
+use crate::interpreter::Interpreter;
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+
+/// Why running a script ended early.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Failure {
+    /// An error in the script, with the message to report.
+    Error(String),
+    /// The script called `exit` with this status.
+    Exit(i32),
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Self {
+        Failure::Error(message)
+    }
+}
+
+impl From<Exception> for Failure {
+    fn from(exception: Exception) -> Self {
+        match exception.exit {
+            Some(status) => Failure::Exit(status),
+            None => Failure::Error(exception.to_string()),
+        }
+    }
+}
+
+/// Scans, parses and runs `source`.
+pub fn run(interpreter: &mut Interpreter, source: &str) -> Result<(), Failure> {
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens()?;
+
+    let mut parser = Parser::new(tokens);
+    let stmts = parser.parse()?;
+    interpreter.interpret(stmts)?;
+
+    Ok(())
+}
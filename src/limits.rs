@@ -0,0 +1,141 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// How much a script may do before it is stopped, for running scripts that
+/// are not trusted. Only the call depth is bounded by default.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Statements executed, including each pass through a loop body.
+    pub max_steps: Option<u64>,
+    /// Lox calls in progress at once. Calls carry on in a freshly allocated
+    /// stack segment when the thread's own runs low, so this holds on a
+    /// thread of any size.
+    pub max_call_depth: usize,
+    /// Bytes in any one string, checked before concatenating and on the
+    /// strings natives return.
+    pub max_string_length: Option<usize>,
+    /// Entries in any one list or map, checked on the containers natives
+    /// such as `push` and `set` are given and on those they return.
+    pub max_collection_len: Option<usize>,
+    /// Wall-clock time, checked between statements, so a native blocked
+    /// on input is not interrupted.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_steps: None,
+            max_call_depth: 1000,
+            max_string_length: None,
+            max_collection_len: None,
+            timeout: None,
+        }
+    }
+}
+
+/// Stack a recursive step needs left to run in place. Below this it moves
+/// onto a new segment of `STACK_SEGMENT` bytes; one Lox call takes tens of
+/// kilobytes of Rust stack in a debug build.
+const STACK_RED_ZONE: usize = 1024 * 1024;
+const STACK_SEGMENT: usize = 8 * 1024 * 1024;
+
+/// Runs `f`, first moving onto a fresh stack segment if the thread's own is
+/// running low. Code that recurses as deep as a script's calls, or as long
+/// as its operator chains, goes through this so it can not overflow.
+pub(crate) fn with_stack<R>(f: impl FnOnce() -> R) -> R {
+    stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT, f)
+}
+
+/// Which limit a script ran into, or that its host interrupted it. Scripts
+/// cannot catch these.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LimitExceeded {
+    Steps(u64),
+    CallDepth(usize),
+    StringLength(usize),
+    CollectionLength(usize),
+    Timeout(Duration),
+    Interrupted,
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LimitExceeded::Steps(max) => write!(f, "Step limit of {} exceeded", max),
+            LimitExceeded::CallDepth(_) => write!(f, "Stack overflow"),
+            LimitExceeded::StringLength(max) => {
+                write!(f, "String longer than the limit of {} bytes", max)
+            }
+            LimitExceeded::CollectionLength(max) => {
+                write!(f, "Collection larger than the limit of {} entries", max)
+            }
+            LimitExceeded::Timeout(timeout) => {
+                write!(f, "Timed out after {}ms", timeout.as_millis())
+            }
+            LimitExceeded::Interrupted => write!(f, "Interrupted"),
+        }
+    }
+}
+
+/// What a run has used of its `Limits` so far, shared by a script and the
+/// modules it imports.
+pub struct Meter {
+    limits: Limits,
+    steps: u64,
+    depth: usize,
+    deadline: Option<Instant>,
+}
+
+impl Meter {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            steps: 0,
+            depth: 0,
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+        }
+    }
+
+    /// Counts one statement. Once a budget is spent every later step fails
+    /// too, so `finally` blocks cannot keep a script going.
+    pub fn step(&mut self) -> Result<(), LimitExceeded> {
+        self.steps += 1;
+        if let Some(max) = self.limits.max_steps.filter(|max| self.steps > *max) {
+            return Err(LimitExceeded::Steps(max));
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.limits.timeout) {
+            if Instant::now() >= deadline {
+                return Err(LimitExceeded::Timeout(timeout));
+            }
+        }
+        Ok(())
+    }
+
+    /// Enters a call, to be paired with `leave`.
+    pub fn enter(&mut self) -> Result<(), LimitExceeded> {
+        if self.depth >= self.limits.max_call_depth {
+            return Err(LimitExceeded::CallDepth(self.limits.max_call_depth));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    pub fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    pub fn check_string(&self, length: usize) -> Result<(), LimitExceeded> {
+        match self.limits.max_string_length {
+            Some(max) if length > max => Err(LimitExceeded::StringLength(max)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_collection(&self, length: usize) -> Result<(), LimitExceeded> {
+        match self.limits.max_collection_len {
+            Some(max) if length > max => Err(LimitExceeded::CollectionLength(max)),
+            _ => Ok(()),
+        }
+    }
+}
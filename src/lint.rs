@@ -0,0 +1,715 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::ast_json::Json;
+use crate::environment::Environment;
+use crate::expr::{Expr, LiteralValue};
+use crate::interner::Symbol;
+use crate::interpreter::Interpreter;
+use crate::natives::{define_natives, Capabilities};
+use crate::parser::Parser;
+use crate::scanner::{Scanner, Token, TokenType};
+use crate::stmt::Stmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    UnusedVariable,
+    Shadowing,
+    UndeclaredAssignment,
+    UnreachableCode,
+    SelfComparison,
+    ConstantCondition,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 6] = [
+        Rule::UnusedVariable,
+        Rule::Shadowing,
+        Rule::UndeclaredAssignment,
+        Rule::UnreachableCode,
+        Rule::SelfComparison,
+        Rule::ConstantCondition,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::Shadowing => "shadowing",
+            Rule::UndeclaredAssignment => "undeclared-assignment",
+            Rule::UnreachableCode => "unreachable-code",
+            Rule::SelfComparison => "self-comparison",
+            Rule::ConstantCondition => "constant-condition",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.name() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub rule: Rule,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}: {} [{}]",
+            self.line,
+            self.message,
+            self.rule.name()
+        )
+    }
+}
+
+/// Which rules run. Every rule is on unless the config file turns it off
+/// with a `rule-name = off` line; `#` starts a comment.
+#[derive(Debug, Default)]
+pub struct Config {
+    disabled: HashSet<Rule>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Config::default();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (name, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected 'rule = on|off'", number + 1))?;
+            let rule = Rule::from_name(name.trim())
+                .ok_or_else(|| format!("line {}: unknown rule '{}'", number + 1, name.trim()))?;
+            match value.trim() {
+                "on" => config.disabled.remove(&rule),
+                "off" => config.disabled.insert(rule),
+                other => {
+                    return Err(format!(
+                        "line {}: expected 'on' or 'off' but got '{}'",
+                        number + 1,
+                        other
+                    ))
+                }
+            };
+        }
+        Ok(config)
+    }
+
+    pub fn is_enabled(&self, rule: Rule) -> bool {
+        !self.disabled.contains(&rule)
+    }
+}
+
+/// Warnings for a parsed program, in line order.
+pub fn lint(stmts: &[Stmt], config: &Config) -> Vec<Warning> {
+    let mut builtins = Environment::new();
+    define_natives(&mut builtins, Capabilities::all(), vec![]);
+
+    let mut linter = Linter {
+        config,
+        warnings: vec![],
+        builtins: builtins
+            .values()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect(),
+        globals: stmts.iter().filter_map(declared_name).collect(),
+        scopes: vec![HashMap::new()],
+        function_depth: 0,
+        evaluator: None,
+    };
+    linter.statements(stmts);
+
+    let mut warnings = linter.warnings;
+    warnings.sort_by_key(|warning| warning.line);
+    warnings
+}
+
+fn declared_name(stmt: &Stmt) -> Option<Symbol> {
+    match stmt {
+        Stmt::Function { name, .. } | Stmt::Import { name, .. } | Stmt::Var { name, .. } => {
+            Some(name.lexeme.symbol())
+        }
+        _ => None,
+    }
+}
+
+struct Binding {
+    line: usize,
+    used: bool,
+    /// Only `var`s are reported when unused; parameters, functions and
+    /// imports often exist for their signature or side effects.
+    report_unused: bool,
+}
+
+struct Linter<'a> {
+    config: &'a Config,
+    warnings: Vec<Warning>,
+    builtins: HashSet<Symbol>,
+    /// Every top-level name, since function bodies run after the whole
+    /// script has been declared.
+    globals: HashSet<Symbol>,
+    scopes: Vec<HashMap<Symbol, Binding>>,
+    function_depth: usize,
+    evaluator: Option<Interpreter>,
+}
+
+impl Linter<'_> {
+    fn warn(&mut self, rule: Rule, line: usize, message: String) {
+        if self.config.is_enabled(rule) {
+            self.warnings.push(Warning {
+                rule,
+                line,
+                message,
+            });
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap_or_default();
+        for (name, binding) in scope {
+            if binding.report_unused && !binding.used && !name.starts_with('_') {
+                self.warn(
+                    Rule::UnusedVariable,
+                    binding.line,
+                    format!("Local variable '{}' is never read", name),
+                );
+            }
+        }
+    }
+
+    fn declare(&mut self, name: &Token, report_unused: bool) {
+        let depth = self.scopes.len() - 1;
+        if depth > 0 {
+            let outer = self.scopes[..depth]
+                .iter()
+                .rev()
+                .find_map(|scope| scope.get(&name.lexeme.symbol()));
+            if let Some(outer) = outer {
+                let message = format!(
+                    "'{}' shadows the variable declared on line {}",
+                    name.lexeme, outer.line
+                );
+                self.warn(Rule::Shadowing, name.line_number, message);
+            }
+        }
+
+        self.scopes[depth].insert(
+            name.lexeme.symbol(),
+            Binding {
+                line: name.line_number,
+                used: false,
+                report_unused: report_unused && depth > 0,
+            },
+        );
+    }
+
+    fn lookup(&mut self, name: Symbol) -> Option<&mut Binding> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(&name))
+    }
+
+    fn is_declared(&mut self, name: Symbol) -> bool {
+        self.lookup(name).is_some()
+            || self.builtins.contains(&name)
+            || (self.function_depth > 0 && self.globals.contains(&name))
+    }
+
+    fn statements(&mut self, stmts: &[Stmt]) {
+        let mut terminated = false;
+        let mut reported = false;
+        for stmt in stmts {
+            if terminated && !reported {
+                if let Some(line) = stmt.line() {
+                    self.warn(Rule::UnreachableCode, line, "Unreachable code".to_string());
+                    reported = true;
+                }
+            }
+            self.stmt(stmt);
+            terminated |= terminates(stmt);
+        }
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) {
+        self.begin_scope();
+        self.statements(stmts);
+        self.end_scope();
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block { statements } => self.block(statements),
+            Stmt::Test { body, .. } => self.block(body),
+            Stmt::Break { .. } | Stmt::Continue { .. } => {}
+            Stmt::Expression { expression } | Stmt::Print { expression } => self.expr(expression),
+            Stmt::Function { name, params, body } => {
+                self.declare(name, false);
+                self.function_depth += 1;
+                self.begin_scope();
+                for param in params {
+                    self.declare(param, false);
+                }
+                self.statements(body);
+                self.end_scope();
+                self.function_depth -= 1;
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.condition(condition);
+                self.stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.stmt(else_branch);
+                }
+            }
+            Stmt::Import { name, .. } => self.declare(name, false),
+            Stmt::Return { value, .. } | Stmt::Throw { value, .. } => self.expr(value),
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.block(body);
+                if let Some((name, handler)) = catch {
+                    self.begin_scope();
+                    self.declare(name, false);
+                    self.statements(handler);
+                    self.end_scope();
+                }
+                if let Some(finally) = finally {
+                    self.block(finally);
+                }
+            }
+            Stmt::Var { name, initializer } => {
+                self.expr(initializer);
+                self.declare(name, true);
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => {
+                // `while (true)` is how a loop says it exits through `break`.
+                if !matches!(
+                    condition,
+                    Expr::Literal {
+                        value: LiteralValue::True,
+                        ..
+                    }
+                ) {
+                    self.condition(condition);
+                } else {
+                    self.expr(condition);
+                }
+                self.stmt(body);
+                if let Some(increment) = increment {
+                    self.expr(increment);
+                }
+            }
+        }
+    }
+
+    fn condition(&mut self, condition: &Expr) {
+        if is_constant(condition) {
+            let evaluator = self.evaluator.get_or_insert_with(Interpreter::new);
+            let message = match condition.evaluate(evaluator) {
+                Ok(value) => format!("Condition is always {}", value.is_truthy()),
+                Err(_) => "Condition is constant".to_string(),
+            };
+            self.warn(Rule::ConstantCondition, condition.line(), message);
+        }
+        self.expr(condition);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { name, value, .. } => {
+                self.expr(value);
+                if !self.is_declared(name.lexeme.symbol()) {
+                    let message = format!("Assignment to undeclared variable '{}'", name.lexeme);
+                    self.warn(Rule::UndeclaredAssignment, name.line_number, message);
+                }
+            }
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                if let Some(result) = self_comparison(operator.token_type) {
+                    if is_pure(left) && left.to_string() == right.to_string() {
+                        let message =
+                            format!("Comparing '{}' with itself is always {}", left, result);
+                        self.warn(Rule::SelfComparison, operator.line_number, message);
+                    }
+                }
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expr(callee);
+                for argument in arguments {
+                    self.expr(argument);
+                }
+            }
+            Expr::Get { object, .. } => self.expr(object),
+            Expr::Grouping { expression } => self.expr(expression),
+            Expr::Literal { .. } => {}
+            Expr::Unary { right, .. } => self.expr(right),
+            Expr::Variable { name, .. } => {
+                if let Some(binding) = self.lookup(name.lexeme.symbol()) {
+                    binding.used = true;
+                }
+            }
+        }
+    }
+}
+
+/// Whether nothing after `stmt` in the same block can run.
+fn terminates(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Break { .. } | Stmt::Continue { .. } | Stmt::Return { .. } | Stmt::Throw { .. } => {
+            true
+        }
+        Stmt::Block { statements } => statements.iter().any(terminates),
+        Stmt::If {
+            then_branch,
+            else_branch: Some(else_branch),
+            ..
+        } => terminates(then_branch) && terminates(else_branch),
+        Stmt::Try {
+            body,
+            catch,
+            finally,
+        } => {
+            finally
+                .as_ref()
+                .is_some_and(|finally| finally.iter().any(terminates))
+                || (body.iter().any(terminates)
+                    && catch
+                        .as_ref()
+                        .is_none_or(|(_, handler)| handler.iter().any(terminates)))
+        }
+        _ => false,
+    }
+}
+
+/// What comparing an expression with itself using `operator` yields, if
+/// `operator` is a comparison.
+fn self_comparison(operator: TokenType) -> Option<bool> {
+    match operator {
+        TokenType::EqualEqual | TokenType::LessEqual | TokenType::GreaterEqual => Some(true),
+        TokenType::BangEqual | TokenType::Less | TokenType::Greater => Some(false),
+        _ => None,
+    }
+}
+
+/// Whether evaluating `expr` twice is sure to give the same value.
+fn is_pure(expr: &Expr) -> bool {
+    match expr {
+        Expr::Assign { .. } | Expr::Call { .. } => false,
+        Expr::Binary { left, right, .. } => is_pure(left) && is_pure(right),
+        Expr::Get { object, .. } => is_pure(object),
+        Expr::Grouping { expression } => is_pure(expression),
+        Expr::Unary { right, .. } => is_pure(right),
+        Expr::Literal { .. } | Expr::Variable { .. } => true,
+    }
+}
+
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal { .. } => true,
+        Expr::Binary { left, right, .. } => is_constant(left) && is_constant(right),
+        Expr::Grouping { expression } => is_constant(expression),
+        Expr::Unary { right, .. } => is_constant(right),
+        _ => false,
+    }
+}
+
+/// Read when no `--config` is given and it exists in the current directory.
+const DEFAULT_CONFIG: &str = ".jloxlint";
+
+const USAGE: &str = "Usage: jlox lint [--json] [--config file] <file>...";
+
+/// `jlox lint`: prints the warnings for each file, as text or as a JSON
+/// array, and the errors of files that do not parse to stderr. Fails if
+/// there were any of either.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut json = false;
+    let mut config_path = None;
+    let mut files = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--json" => json = true,
+            "--config" => config_path = Some(args.next().ok_or(USAGE)?.as_str()),
+            _ => files.push(arg.as_str()),
+        }
+    }
+    if files.is_empty() {
+        return Err(USAGE.to_string());
+    }
+
+    let config = match config_path {
+        Some(path) => read_config(path)?,
+        None if Path::new(DEFAULT_CONFIG).exists() => read_config(DEFAULT_CONFIG)?,
+        None => Config::default(),
+    };
+
+    let summary = lint_files(&files, &config, json, &mut io::stdout(), &mut io::stderr());
+    match summary {
+        Summary {
+            warnings: 0,
+            failed: 0,
+        } => Ok(()),
+        Summary {
+            warnings,
+            failed: 0,
+        } => Err(format!("{} warning(s)", warnings)),
+        Summary { warnings, failed } => Err(format!(
+            "{} warning(s), {} file(s) could not be linted",
+            warnings, failed
+        )),
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    pub warnings: usize,
+    /// Files that could not be read or parsed.
+    pub failed: usize,
+}
+
+/// Lints each of `files`, writing their warnings to `out`. A file that can
+/// not be read or parsed has its errors written to `errors`, as
+/// `file:line: message`, and the rest are linted all the same.
+pub fn lint_files(
+    files: &[&str],
+    config: &Config,
+    json: bool,
+    out: &mut impl Write,
+    errors: &mut impl Write,
+) -> Summary {
+    let mut summary = Summary::default();
+    let mut report = vec![];
+    for file in files {
+        let stmts = match parse_file(file) {
+            Ok(stmts) => stmts,
+            Err(messages) => {
+                for message in messages {
+                    let _ = writeln!(errors, "{}", message);
+                }
+                summary.failed += 1;
+                continue;
+            }
+        };
+
+        for warning in lint(&stmts, config) {
+            summary.warnings += 1;
+            if json {
+                report.push(Json::Object(vec![
+                    ("file", Json::Str(file.to_string())),
+                    ("line", Json::Number(warning.line as f32)),
+                    ("rule", Json::Str(warning.rule.name().to_string())),
+                    ("message", Json::Str(warning.message)),
+                ]));
+            } else {
+                let _ = writeln!(out, "{}: {}", file, warning);
+            }
+        }
+    }
+
+    if json {
+        let _ = writeln!(out, "{}", Json::Array(report).render());
+    }
+    summary
+}
+
+/// The statements in `file`, or its errors as `file:line: message`.
+fn parse_file(file: &str) -> Result<Vec<Stmt>, Vec<String>> {
+    let source = fs::read_to_string(file)
+        .map_err(|err| vec![format!("{}: could not read: {}", file, err)])?;
+    Scanner::new(&source)
+        .scan_tokens()
+        .and_then(|tokens| Parser::new(tokens).parse())
+        .map_err(|errors| {
+            errors
+                .lines()
+                .map(|error| match error_line(error) {
+                    Some(line) => {
+                        let prefix = format!("[line {}] ", line);
+                        let message = error.strip_prefix(&prefix).unwrap_or(error);
+                        format!("{}:{}: {}", file, line, message)
+                    }
+                    None => format!("{}: {}", file, error),
+                })
+                .collect()
+        })
+}
+
+/// The `N` of the first `line N` in a scanner or parser error message.
+pub(crate) fn error_line(message: &str) -> Option<usize> {
+    let (_, rest) = message.split_once("line ")?;
+    let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+    digits.parse().ok()
+}
+
+fn read_config(path: &str) -> Result<Config, String> {
+    let text =
+        fs::read_to_string(path).map_err(|err| format!("could not read '{}': {}", path, err))?;
+    Config::parse(&text).map_err(|err| format!("{}: {}", path, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn warnings(source: &str, config: &Config) -> Vec<String> {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        lint(&stmts, config)
+            .iter()
+            .map(Warning::to_string)
+            .collect()
+    }
+
+    fn check(source: &str) -> Vec<String> {
+        warnings(source, &Config::default())
+    }
+
+    #[test]
+    fn reports_unused_and_shadowing_variables() {
+        let source = "var a = 1;\n\
+                      fun f(p) {\n  var a = 2;\n  var b = 3;\n  var _c = 4;\n  return a;\n}\n\
+                      print f(a);";
+
+        assert_eq!(
+            check(source),
+            vec![
+                "line 3: 'a' shadows the variable declared on line 1 [shadowing]",
+                "line 4: Local variable 'b' is never read [unused-variable]",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_assignments_to_undeclared_names() {
+        let source = "x = 1;\nvar y;\ny = 2;\nfun f() { z = 3; later = 4; }\nvar later;";
+
+        assert_eq!(
+            check(source),
+            vec![
+                "line 1: Assignment to undeclared variable 'x' [undeclared-assignment]",
+                "line 4: Assignment to undeclared variable 'z' [undeclared-assignment]",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unreachable_code() {
+        let source =
+            "fun f(x) {\n  if (x) { return 1; } else { throw 2; }\n  print 3;\n  print 4;\n}\n\
+                      while (f(1)) {\n  break;\n  print 5;\n}";
+
+        assert_eq!(
+            check(source),
+            vec![
+                "line 3: Unreachable code [unreachable-code]",
+                "line 8: Unreachable code [unreachable-code]",
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_self_comparisons_and_constant_conditions() {
+        let source = "var x = 1;\nprint x == x;\nprint x() < x();\n\
+                      if (1 > 2) print x;\nwhile (true) break;\nwhile (!nil) break;";
+
+        assert_eq!(
+            check(source),
+            vec![
+                "line 2: Comparing '(var x)' with itself is always true [self-comparison]",
+                "line 4: Condition is always false [constant-condition]",
+                "line 6: Condition is always true [constant-condition]",
+            ]
+        );
+    }
+
+    #[test]
+    fn config_disables_rules() {
+        let config = Config::parse("# quiet\nself-comparison = off\nshadowing=on\n").unwrap();
+
+        assert!(!config.is_enabled(Rule::SelfComparison));
+        assert!(config.is_enabled(Rule::Shadowing));
+        assert!(warnings("var x;\nprint x == x;", &config).is_empty());
+
+        assert_eq!(
+            Config::parse("typo = off").unwrap_err(),
+            "line 1: unknown rule 'typo'"
+        );
+    }
+
+    #[test]
+    fn carries_on_past_files_that_do_not_parse() {
+        let dir = std::env::temp_dir().join("jlox_lint_broken");
+        fs::create_dir_all(&dir).unwrap();
+        let files: Vec<String> = [
+            ("scan.lox", "var a = \"open;\n"),
+            ("parse.lox", "var a = 1;\nvar b = ;\n"),
+            ("fine.lox", "fun f() { var unused = 1; }\n"),
+        ]
+        .iter()
+        .map(|(name, source)| {
+            let path = dir.join(name);
+            fs::write(&path, source).unwrap();
+            path.display().to_string()
+        })
+        .collect();
+        let files: Vec<&str> = files.iter().map(String::as_str).collect();
+
+        let (mut out, mut errors) = (vec![], vec![]);
+        let summary = lint_files(&files, &Config::default(), false, &mut out, &mut errors);
+
+        assert_eq!(
+            summary,
+            Summary {
+                warnings: 1,
+                failed: 2
+            }
+        );
+        assert_eq!(
+            String::from_utf8(errors).unwrap(),
+            format!(
+                "{}:1: Unterminated string at line 1\n{}:2: Expected expression\n",
+                files[0], files[1]
+            )
+        );
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "{}: line 1: Local variable 'unused' is never read [unused-variable]\n",
+                files[2]
+            )
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
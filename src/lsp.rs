@@ -0,0 +1,877 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
+
+use serde_json::{json, Value};
+
+use crate::environment::Environment;
+use crate::expr::{Expr, LiteralValue};
+use crate::interner::Symbol;
+use crate::lint::{self, error_line, Config};
+use crate::natives::{define_natives, Capabilities};
+use crate::parser::Parser;
+use crate::scanner::{Scanner, Token, TokenType, KEYWORDS};
+use crate::stmt::Stmt;
+
+// LSP enum values.
+const SEVERITY_ERROR: u8 = 1;
+const SEVERITY_WARNING: u8 = 2;
+const SYMBOL_MODULE: u8 = 2;
+const SYMBOL_FUNCTION: u8 = 12;
+const SYMBOL_VARIABLE: u8 = 13;
+const COMPLETION_FUNCTION: u8 = 3;
+const COMPLETION_VARIABLE: u8 = 6;
+const COMPLETION_MODULE: u8 = 9;
+const COMPLETION_KEYWORD: u8 = 14;
+const SYNC_FULL: u8 = 1;
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+/// The largest message body read into memory; longer ones are skipped.
+const MAX_MESSAGE: usize = 64 * 1024 * 1024;
+
+/// `jlox lsp`: serves the Language Server Protocol on stdin and stdout
+/// until the client sends `exit`.
+pub fn run() -> Result<(), String> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    serve(&mut stdin.lock(), &mut stdout.lock())
+}
+
+/// Serves until `exit` or the end of `input`. A message that is badly
+/// framed or not JSON gets a parse error back, and serving carries on.
+pub fn serve(input: &mut impl BufRead, output: &mut impl Write) -> Result<(), String> {
+    let mut server = Server::default();
+    while let Some(message) = read_frame(input)? {
+        let replies = match message {
+            Ok(message) => server.handle(&message),
+            Err(msg) => vec![json!({
+                "jsonrpc": "2.0",
+                "id": Value::Null,
+                "error": { "code": PARSE_ERROR, "message": msg },
+            })],
+        };
+        for reply in replies {
+            write_message(output, &reply)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Reads one `Content-Length` framed message, the framing the debug
+/// adapter protocol shares with LSP. `None` means the input has ended.
+pub(crate) fn read_message(input: &mut impl BufRead) -> Result<Option<Value>, String> {
+    read_frame(input)?.transpose()
+}
+
+/// Like `read_message`, but tells a message that could not be read apart,
+/// in the inner result, from input that can not be read any further.
+fn read_frame(input: &mut impl BufRead) -> Result<Option<Result<Value, String>>, String> {
+    let mut length = None;
+    let mut bad_header = None;
+    loop {
+        let mut header = String::new();
+        let read = input.read_line(&mut header).map_err(|e| e.to_string())?;
+        if read == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            match value.trim().parse::<usize>() {
+                Ok(value) => length = Some(value),
+                Err(err) => bad_header = Some(format!("invalid Content-Length: {}", err)),
+            }
+        }
+    }
+
+    let length = match (bad_header, length) {
+        (Some(msg), _) => return Ok(Some(Err(msg))),
+        (None, None) => {
+            return Ok(Some(Err(
+                "message without a Content-Length header".to_string()
+            )))
+        }
+        (None, Some(length)) if length > MAX_MESSAGE => {
+            io::copy(&mut Read::take(&mut *input, length as u64), &mut io::sink())
+                .map_err(|e| e.to_string())?;
+            return Ok(Some(Err(format!(
+                "message of {} bytes is over the limit of {}",
+                length, MAX_MESSAGE
+            ))));
+        }
+        (None, Some(length)) => length,
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).map_err(|e| e.to_string())?;
+    Ok(Some(
+        serde_json::from_slice(&body).map_err(|e| format!("invalid message: {}", e)),
+    ))
+}
+
+pub(crate) fn write_message(output: &mut impl Write, message: &Value) -> Result<(), String> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+        .and_then(|_| output.flush())
+        .map_err(|e| e.to_string())
+}
+
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    exited: bool,
+}
+
+impl Server {
+    /// Everything to send back for one incoming message: the response to a
+    /// request, plus any notifications it triggered.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": SYNC_FULL,
+                    "definitionProvider": true,
+                    "hoverProvider": true,
+                    "documentSymbolProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": "jlox" },
+            }),
+            "shutdown" => Value::Null,
+            "exit" => {
+                self.exited = true;
+                return vec![];
+            }
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                return self.update(uri, text);
+            }
+            "textDocument/didChange" => {
+                let changes = params["contentChanges"].as_array();
+                let text = changes
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                    .unwrap_or("");
+                return self.update(uri, text);
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish(uri, vec![])];
+            }
+            "textDocument/definition" => match self.lookup(uri, &params["position"]) {
+                Some((document, declaration)) => json!({
+                    "uri": uri,
+                    "range": document.range(&declaration.name),
+                }),
+                None => Value::Null,
+            },
+            "textDocument/hover" => match self.lookup(uri, &params["position"]) {
+                Some((document, declaration)) => json!({
+                    "contents": {
+                        "kind": "markdown",
+                        "value": document.describe(declaration),
+                    },
+                }),
+                None => Value::Null,
+            },
+            "textDocument/documentSymbol" => match self.documents.get(uri) {
+                Some(document) => Value::Array(symbols(document, &document.stmts)),
+                None => Value::Null,
+            },
+            "textDocument/completion" => match self.documents.get(uri) {
+                Some(document) => Value::Array(document.completions()),
+                None => Value::Null,
+            },
+            _ if message.get("id").is_some() => {
+                return vec![json!({
+                    "jsonrpc": "2.0",
+                    "id": message["id"],
+                    "error": {
+                        "code": METHOD_NOT_FOUND,
+                        "message": format!("Unsupported method '{}'", method),
+                    },
+                })];
+            }
+            // Notifications we have no use for, like `initialized`.
+            _ => return vec![],
+        };
+
+        vec![json!({ "jsonrpc": "2.0", "id": message["id"], "result": result })]
+    }
+
+    /// Analyzes a new version of a document. While it does not parse, names
+    /// are looked up in the last version that did, so definitions, hovers and
+    /// symbols keep working mid-edit, if only for code that has not moved.
+    fn update(&mut self, uri: &str, text: &str) -> Vec<Value> {
+        let mut document = Document::analyze(text);
+        let diagnostics = document.diagnostics.clone();
+        if !document.parsed {
+            if let Some(previous) = self.documents.remove(uri).filter(|d| d.parsed) {
+                document = Document {
+                    diagnostics: diagnostics.clone(),
+                    ..previous
+                };
+            }
+        }
+        self.documents.insert(uri.to_string(), document);
+        vec![publish(uri, diagnostics)]
+    }
+
+    fn lookup(&self, uri: &str, position: &Value) -> Option<(&Document, &Declaration)> {
+        let document = self.documents.get(uri)?;
+        let line = position["line"].as_u64()? as usize + 1;
+        let units = position["character"].as_u64()? as usize;
+        let column = char_column(document.line(line), units) + 1;
+        let declaration = document.declaration_at(line, column)?;
+        Some((document, declaration))
+    }
+}
+
+fn publish(uri: &str, diagnostics: Vec<Value>) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+/// The column, in the UTF-16 code units LSP counts, after the first
+/// `chars` characters of `line`.
+fn utf16_column(line: &str, chars: usize) -> usize {
+    line.chars().take(chars).map(char::len_utf16).sum()
+}
+
+/// How many whole characters of `line` the first `units` UTF-16 code units
+/// cover.
+fn char_column(line: &str, units: usize) -> usize {
+    let mut end = 0;
+    line.chars()
+        .take_while(|c| {
+            end += c.len_utf16();
+            end <= units
+        })
+        .count()
+}
+
+/// Errors from the scanner and parser only carry a line, so they cover
+/// the whole of it.
+fn line_range(text: &str, line: usize) -> Value {
+    let length = text
+        .lines()
+        .nth(line - 1)
+        .map_or(0, |line| line.encode_utf16().count());
+    json!({
+        "start": { "line": line - 1, "character": 0 },
+        "end": { "line": line - 1, "character": length },
+    })
+}
+
+fn diagnostic(text: &str, line: usize, severity: u8, code: Option<&str>, message: &str) -> Value {
+    let mut diagnostic = json!({
+        "range": line_range(text, line),
+        "severity": severity,
+        "source": "jlox",
+        "message": message,
+    });
+    if let Some(code) = code {
+        diagnostic["code"] = json!(code);
+    }
+    diagnostic
+}
+
+struct Declaration {
+    name: Token,
+    kind: &'static str,
+}
+
+/// What the server knows about one open file.
+struct Document {
+    /// The text the tokens and statements came from.
+    text: String,
+    /// Whether the text parsed. Names are only resolved when it did.
+    parsed: bool,
+    tokens: Vec<Token>,
+    stmts: Vec<Stmt>,
+    diagnostics: Vec<Value>,
+    declarations: Vec<Declaration>,
+    /// Every resolved use of a name, with the declaration it refers to.
+    references: Vec<(Token, usize)>,
+}
+
+impl Document {
+    fn analyze(text: &str) -> Self {
+        let mut document = Document {
+            text: text.to_string(),
+            parsed: false,
+            tokens: vec![],
+            stmts: vec![],
+            diagnostics: vec![],
+            declarations: vec![],
+            references: vec![],
+        };
+
+        let parsed = Scanner::new(text).scan_tokens().and_then(|tokens| {
+            document.tokens = tokens.clone();
+            Parser::new(tokens).parse()
+        });
+        match parsed {
+            Ok(stmts) => {
+                document.stmts = stmts;
+                document.parsed = true;
+            }
+            Err(errors) => {
+                for error in errors.lines() {
+                    let line = error_line(error).unwrap_or(1);
+                    let error = diagnostic(text, line, SEVERITY_ERROR, None, error);
+                    document.diagnostics.push(error);
+                }
+                return document;
+            }
+        }
+
+        for warning in lint::lint(&document.stmts, &Config::default()) {
+            document.diagnostics.push(diagnostic(
+                text,
+                warning.line,
+                SEVERITY_WARNING,
+                Some(warning.rule.name()),
+                &warning.message,
+            ));
+        }
+
+        let mut resolver = Resolver::new();
+        resolver.statements(&document.stmts);
+        resolver.finish();
+        document.declarations = resolver.declarations;
+        document.references = resolver.references;
+        document
+    }
+
+    /// Line `line` of the text, counting from one.
+    fn line(&self, line: usize) -> &str {
+        self.text.lines().nth(line.saturating_sub(1)).unwrap_or("")
+    }
+
+    /// Where `token` is, in LSP's zero-based lines and UTF-16 columns; token
+    /// lines and columns start at one and count characters.
+    fn range(&self, token: &Token) -> Value {
+        let text = self.line(token.line_number);
+        let start = token.column - 1;
+        let end = start + token.lexeme.chars().count();
+        let line = token.line_number - 1;
+        json!({
+            "start": { "line": line, "character": utf16_column(text, start) },
+            "end": { "line": line, "character": utf16_column(text, end) },
+        })
+    }
+
+    fn declaration_at(&self, line: usize, column: usize) -> Option<&Declaration> {
+        let covers = |token: &Token| {
+            token.line_number == line
+                && token.column <= column
+                && column <= token.column + token.lexeme.chars().count()
+        };
+
+        if let Some(declaration) = self.declarations.iter().find(|d| covers(&d.name)) {
+            return Some(declaration);
+        }
+        self.references
+            .iter()
+            .find(|(token, _)| covers(token))
+            .map(|(_, index)| &self.declarations[*index])
+    }
+
+    fn describe(&self, declaration: &Declaration) -> String {
+        let line = declaration.name.line_number;
+        let source = self.text.lines().nth(line - 1).unwrap_or("").trim();
+        format!(
+            "```lox\n{}\n```\n{} `{}`, declared on line {}",
+            source, declaration.kind, declaration.name.lexeme, line
+        )
+    }
+
+    fn completions(&self) -> Vec<Value> {
+        let mut builtins = Environment::new();
+        define_natives(&mut builtins, Capabilities::all(), vec![]);
+
+        let mut items: Vec<(String, u8)> = KEYWORDS
+            .keys()
+            .map(|keyword| (keyword.to_string(), COMPLETION_KEYWORD))
+            .chain(builtins.values().iter().map(|(name, value)| match value {
+                LiteralValue::Namespace(_) => (name.to_string(), COMPLETION_MODULE),
+                _ => (name.to_string(), COMPLETION_FUNCTION),
+            }))
+            .chain(
+                self.tokens
+                    .iter()
+                    .filter(|token| token.token_type == TokenType::Identifier)
+                    .map(|token| (token.lexeme.to_string(), COMPLETION_VARIABLE)),
+            )
+            .collect();
+        items.sort();
+        items.dedup_by(|a, b| a.0 == b.0);
+
+        items
+            .into_iter()
+            .map(|(label, kind)| json!({ "label": label, "kind": kind }))
+            .collect()
+    }
+}
+
+/// Functions, variables and imports as nested `DocumentSymbol`s. Blocks
+/// don't get a symbol of their own; their declarations belong to the
+/// enclosing function, or to the file.
+fn symbols(document: &Document, stmts: &[Stmt]) -> Vec<Value> {
+    let mut out = vec![];
+    for stmt in stmts {
+        collect_symbols(document, stmt, &mut out);
+    }
+    out
+}
+
+fn collect_symbols(document: &Document, stmt: &Stmt, out: &mut Vec<Value>) {
+    let symbol = |name: &Token, kind: u8, children: Vec<Value>| {
+        json!({
+            "name": name.lexeme.as_str(),
+            "kind": kind,
+            "range": document.range(name),
+            "selectionRange": document.range(name),
+            "children": children,
+        })
+    };
+    let symbols = |stmts: &[Stmt]| symbols(document, stmts);
+
+    match stmt {
+        Stmt::Var { name, .. } => out.push(symbol(name, SYMBOL_VARIABLE, vec![])),
+        Stmt::Import { name, .. } => out.push(symbol(name, SYMBOL_MODULE, vec![])),
+        Stmt::Function { name, body, .. } => out.push(symbol(name, SYMBOL_FUNCTION, symbols(body))),
+        Stmt::Test { name, body } => out.push(symbol(name, SYMBOL_FUNCTION, symbols(body))),
+        Stmt::Block { statements } => out.extend(symbols(statements)),
+        Stmt::If {
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            collect_symbols(document, then_branch, out);
+            if let Some(else_branch) = else_branch {
+                collect_symbols(document, else_branch, out);
+            }
+        }
+        Stmt::While { body, .. } => collect_symbols(document, body, out),
+        Stmt::Try {
+            body,
+            catch,
+            finally,
+        } => {
+            out.extend(symbols(body));
+            if let Some((_, handler)) = catch {
+                out.extend(symbols(handler));
+            }
+            if let Some(finally) = finally {
+                out.extend(symbols(finally));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Links every use of a name to its declaration, following the same
+/// scoping rules as the interpreter.
+struct Resolver {
+    declarations: Vec<Declaration>,
+    references: Vec<(Token, usize)>,
+    scopes: Vec<HashMap<Symbol, usize>>,
+    function_depth: usize,
+    /// Uses inside functions that may name a global declared further down.
+    pending: Vec<Token>,
+}
+
+impl Resolver {
+    fn new() -> Self {
+        Self {
+            declarations: vec![],
+            references: vec![],
+            scopes: vec![HashMap::new()],
+            function_depth: 0,
+            pending: vec![],
+        }
+    }
+
+    fn declare(&mut self, name: &Token, kind: &'static str) {
+        self.declarations.push(Declaration {
+            name: name.clone(),
+            kind,
+        });
+        let index = self.declarations.len() - 1;
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.symbol(), index);
+        }
+    }
+
+    fn reference(&mut self, name: &Token) {
+        let found = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme.symbol()).copied());
+        match found {
+            Some(index) => self.references.push((name.clone(), index)),
+            None if self.function_depth > 0 => self.pending.push(name.clone()),
+            None => {}
+        }
+    }
+
+    fn finish(&mut self) {
+        let globals = self.scopes.first().cloned().unwrap_or_default();
+        for name in self.pending.drain(..) {
+            if let Some(index) = globals.get(&name.lexeme.symbol()) {
+                self.references.push((name, *index));
+            }
+        }
+    }
+
+    fn scoped(&mut self, body: impl FnOnce(&mut Self)) {
+        self.scopes.push(HashMap::new());
+        body(self);
+        self.scopes.pop();
+    }
+
+    fn statements(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block { statements } => self.scoped(|r| r.statements(statements)),
+            Stmt::Test { body, .. } => self.scoped(|r| r.statements(body)),
+            Stmt::Break { .. } | Stmt::Continue { .. } => {}
+            Stmt::Expression { expression } | Stmt::Print { expression } => self.expr(expression),
+            Stmt::Function { name, params, body } => {
+                self.declare(name, "function");
+                self.function_depth += 1;
+                self.scoped(|r| {
+                    for param in params {
+                        r.declare(param, "parameter");
+                    }
+                    r.statements(body);
+                });
+                self.function_depth -= 1;
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(condition);
+                self.stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.stmt(else_branch);
+                }
+            }
+            Stmt::Import { name, .. } => self.declare(name, "module"),
+            Stmt::Return { value, .. } | Stmt::Throw { value, .. } => self.expr(value),
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.scoped(|r| r.statements(body));
+                if let Some((name, handler)) = catch {
+                    self.scoped(|r| {
+                        r.declare(name, "caught exception");
+                        r.statements(handler);
+                    });
+                }
+                if let Some(finally) = finally {
+                    self.scoped(|r| r.statements(finally));
+                }
+            }
+            Stmt::Var { name, initializer } => {
+                self.expr(initializer);
+                self.declare(name, "variable");
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => {
+                self.expr(condition);
+                self.stmt(body);
+                if let Some(increment) = increment {
+                    self.expr(increment);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { name, value, .. } => {
+                self.expr(value);
+                self.reference(name);
+            }
+            Expr::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expr(callee);
+                for argument in arguments {
+                    self.expr(argument);
+                }
+            }
+            Expr::Get { object, .. } => self.expr(object),
+            Expr::Grouping { expression } => self.expr(expression),
+            Expr::Literal { .. } => {}
+            Expr::Unary { right, .. } => self.expr(right),
+            Expr::Variable { name, .. } => self.reference(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///project/main.lox";
+
+    /// A session recorded from an editor, one JSON-RPC message per line.
+    const SESSION: &str = r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"processId":null,"rootUri":null,"capabilities":{}}}
+{"jsonrpc":"2.0","method":"initialized","params":{}}
+{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///project/main.lox","languageId":"lox","version":1,"text":"fun add(a, b) {\n  return a + b;\n}\nvar total = add(1, 2)\n"}}}
+{"jsonrpc":"2.0","method":"textDocument/didChange","params":{"textDocument":{"uri":"file:///project/main.lox","version":2},"contentChanges":[{"text":"fun add(a, b) {\n  return a + b;\n}\nvar total = add(1, 2);\nprint total;\n"}]}}
+{"jsonrpc":"2.0","id":2,"method":"textDocument/definition","params":{"textDocument":{"uri":"file:///project/main.lox"},"position":{"line":4,"character":8}}}
+{"jsonrpc":"2.0","id":3,"method":"textDocument/hover","params":{"textDocument":{"uri":"file:///project/main.lox"},"position":{"line":1,"character":9}}}
+{"jsonrpc":"2.0","id":4,"method":"textDocument/documentSymbol","params":{"textDocument":{"uri":"file:///project/main.lox"}}}
+{"jsonrpc":"2.0","id":5,"method":"textDocument/completion","params":{"textDocument":{"uri":"file:///project/main.lox"},"position":{"line":4,"character":0}}}
+{"jsonrpc":"2.0","id":6,"method":"textDocument/formatting","params":{"textDocument":{"uri":"file:///project/main.lox"},"options":{}}}
+{"jsonrpc":"2.0","id":7,"method":"shutdown"}
+{"jsonrpc":"2.0","method":"exit"}
+{"jsonrpc":"2.0","id":8,"method":"shutdown"}"#;
+
+    fn replay(session: &str) -> Vec<Value> {
+        let mut input = vec![];
+        for line in session.lines() {
+            write!(input, "Content-Length: {}\r\n\r\n{}", line.len(), line).unwrap();
+        }
+
+        let mut output = vec![];
+        serve(&mut input.as_slice(), &mut output).unwrap();
+
+        let mut replies = vec![];
+        let mut output = output.as_slice();
+        while let Some(reply) = read_message(&mut output).unwrap() {
+            replies.push(reply);
+        }
+        replies
+    }
+
+    #[test]
+    fn replays_recorded_session() {
+        let replies = replay(SESSION);
+        assert_eq!(replies.len(), 9);
+
+        assert_eq!(replies[0]["id"], 1);
+        assert_eq!(replies[0]["result"]["capabilities"]["hoverProvider"], true);
+
+        let diagnostics = &replies[1]["params"]["diagnostics"];
+        assert_eq!(replies[1]["params"]["uri"], URI);
+        assert_eq!(diagnostics[0]["severity"], SEVERITY_ERROR);
+        assert_eq!(diagnostics[0]["range"]["start"]["line"], 4);
+        assert_eq!(
+            replies[2]["params"]["diagnostics"],
+            json!([]),
+            "the fixed document has no diagnostics"
+        );
+
+        assert_eq!(
+            replies[3]["result"],
+            json!({
+                "uri": URI,
+                "range": {
+                    "start": { "line": 3, "character": 4 },
+                    "end": { "line": 3, "character": 9 },
+                },
+            })
+        );
+
+        assert_eq!(
+            replies[4]["result"]["contents"]["value"],
+            "```lox\nfun add(a, b) {\n```\nparameter `a`, declared on line 1"
+        );
+
+        let symbols = replies[5]["result"].as_array().unwrap();
+        let names: Vec<&str> = symbols
+            .iter()
+            .map(|s| s["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["add", "total"]);
+        assert_eq!(symbols[0]["kind"], SYMBOL_FUNCTION);
+
+        let items = replies[6]["result"].as_array().unwrap();
+        let item = |label: &str| items.iter().find(|item| item["label"] == label).cloned();
+        assert_eq!(item("while").unwrap()["kind"], COMPLETION_KEYWORD);
+        assert_eq!(item("total").unwrap()["kind"], COMPLETION_VARIABLE);
+        assert_eq!(item("readFile").unwrap()["kind"], COMPLETION_FUNCTION);
+        assert_eq!(item("json").unwrap()["kind"], COMPLETION_MODULE);
+
+        assert_eq!(replies[7]["error"]["code"], METHOD_NOT_FOUND);
+        assert_eq!(
+            replies[8],
+            json!({ "jsonrpc": "2.0", "id": 7, "result": null })
+        );
+    }
+
+    #[test]
+    fn resolves_names_by_scope() {
+        let mut server = Server::default();
+        let source = "var a = 1;\nfun f() {\n  var a = 2;\n  print a + b;\n}\nvar b = a;";
+        server.handle(&json!({
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": URI, "text": source } },
+        }));
+
+        let mut definition = |line: usize, character: usize| {
+            let reply = server.handle(&json!({
+                "id": 1,
+                "method": "textDocument/definition",
+                "params": {
+                    "textDocument": { "uri": URI },
+                    "position": { "line": line, "character": character },
+                },
+            }));
+            reply[0]["result"]["range"]["start"]["line"].clone()
+        };
+
+        assert_eq!(definition(3, 8), 2, "the local shadows the global");
+        assert_eq!(definition(3, 12), 5, "globals declared later are visible");
+        assert_eq!(definition(5, 8), 0);
+        assert_eq!(definition(3, 6), Value::Null, "not on a name");
+    }
+
+    #[test]
+    fn reports_lint_warnings() {
+        let mut server = Server::default();
+        let replies = server.handle(&json!({
+            "method": "textDocument/didOpen",
+            "params": {
+                "textDocument": { "uri": URI, "text": "fun f() {\n  var unused;\n}" },
+            },
+        }));
+
+        let diagnostic = &replies[0]["params"]["diagnostics"][0];
+        assert_eq!(diagnostic["severity"], SEVERITY_WARNING);
+        assert_eq!(diagnostic["code"], "unused-variable");
+        assert_eq!(diagnostic["range"]["start"]["line"], 1);
+    }
+
+    fn open(server: &mut Server, text: &str) {
+        server.handle(&json!({
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": URI, "text": text } },
+        }));
+    }
+
+    fn definition(server: &mut Server, line: usize, character: usize) -> Value {
+        let reply = server.handle(&json!({
+            "id": 1,
+            "method": "textDocument/definition",
+            "params": {
+                "textDocument": { "uri": URI },
+                "position": { "line": line, "character": character },
+            },
+        }));
+        reply[0]["result"]["range"].clone()
+    }
+
+    #[test]
+    fn keeps_serving_after_bad_messages() {
+        let shutdown = r#"{"jsonrpc":"2.0","id":1,"method":"shutdown"}"#;
+        let input = format!(
+            "Content-Length: 5\r\n\r\n{{oops\
+             Content-Length: x\r\n\r\n\
+             Content-Type: text\r\n\r\n\
+             Content-Length: {}\r\n\r\n{}",
+            shutdown.len(),
+            shutdown
+        );
+
+        let mut output = vec![];
+        serve(&mut input.as_bytes(), &mut output).unwrap();
+
+        let mut replies = vec![];
+        let mut output = output.as_slice();
+        while let Some(reply) = read_message(&mut output).unwrap() {
+            replies.push(reply);
+        }
+        assert_eq!(replies.len(), 4);
+        for reply in &replies[..3] {
+            assert_eq!(reply["id"], Value::Null);
+            assert_eq!(reply["error"]["code"], PARSE_ERROR);
+        }
+        assert_eq!(
+            replies[3],
+            json!({ "jsonrpc": "2.0", "id": 1, "result": null })
+        );
+    }
+
+    #[test]
+    fn skips_messages_over_the_size_limit() {
+        let input = "Content-Length: 99999999999999\r\n\r\n{}";
+
+        let mut output = vec![];
+        serve(&mut input.as_bytes(), &mut output).unwrap();
+
+        let reply = read_message(&mut output.as_slice()).unwrap().unwrap();
+        assert_eq!(reply["error"]["code"], PARSE_ERROR);
+        assert_eq!(
+            reply["error"]["message"],
+            "message of 99999999999999 bytes is over the limit of 67108864"
+        );
+    }
+
+    #[test]
+    fn positions_count_utf16_code_units() {
+        let mut server = Server::default();
+        open(&mut server, "var s = \"😀\"; var x = 1;\nprint \"😀\" + x;");
+
+        let declared = json!({
+            "start": { "line": 0, "character": 18 },
+            "end": { "line": 0, "character": 19 },
+        });
+        assert_eq!(definition(&mut server, 1, 13), declared);
+        assert_eq!(definition(&mut server, 0, 18), declared);
+        assert_eq!(definition(&mut server, 0, 17), Value::Null);
+    }
+
+    #[test]
+    fn falls_back_on_the_last_version_that_parsed() {
+        let mut server = Server::default();
+        open(&mut server, "var a = 1;\nprint a;");
+        let replies = server.handle(&json!({
+            "method": "textDocument/didChange",
+            "params": {
+                "textDocument": { "uri": URI },
+                "contentChanges": [{ "text": "var a = 1;\nprint a;\nprint (" }],
+            },
+        }));
+
+        assert_eq!(
+            replies[0]["params"]["diagnostics"][0]["severity"],
+            SEVERITY_ERROR
+        );
+        assert_eq!(definition(&mut server, 1, 6)["start"]["line"], 0);
+    }
+}
@@ -1,89 +1,214 @@
+use std::cell::RefCell;
 use std::env;
 use std::fs;
-use std::io::{self, BufRead, Write};
+use std::io::{self, Write};
+use std::path::Path;
 use std::process::exit;
+use std::rc::Rc;
 use std::result::Result;
+use std::thread;
 
-use crate::interpreter::*;
-use crate::parser::*;
-use crate::scanner::*;
-use crate::stmt::Stmt::*;
-
-mod environment;
-mod expr;
-mod interpreter;
-mod parser;
-mod scanner;
-mod stmt;
-
-fn run_file(path: &str) -> Result<(), String> {
-    let mut interpreter = Interpreter::new();
-    match fs::read_to_string(path) {
-        Ok(contents) => run(&mut interpreter, &contents),
-        Err(_) => Err("ERROR: could not run file".to_string()),
+use jlox::interpreter::*;
+use jlox::natives::Capabilities;
+use jlox::parser::*;
+use jlox::scanner::*;
+use jlox::{
+    ast_json, coverage, dap, debugger, formatter, lint, lsp, optimizer, profiler, repl, run,
+    testing, Failure,
+};
+
+fn run_file(path: &str, args: Vec<String>, optimize: bool) -> Result<(), Failure> {
+    let source = fs::read_to_string(path).map_err(|_| "ERROR: could not run file".to_string())?;
+    let mut interpreter = Interpreter::with_capabilities(Capabilities::all(), args);
+    interpreter.set_file(Path::new(path));
+
+    let tokens = Scanner::new(&source).scan_tokens()?;
+    let mut stmts = Parser::new(tokens).parse()?;
+    if optimize {
+        stmts = optimizer::optimize(stmts);
     }
+    Ok(interpreter.interpret(stmts)?)
 }
 
-fn run(interpreter: &mut Interpreter, source: &str) -> Result<(), String> {
-    let mut scanner = Scanner::new(source);
-    let tokens = scanner.scan_tokens()?;
+fn debug_file(path: &str, args: Vec<String>) -> Result<(), Failure> {
+    let source = fs::read_to_string(path).map_err(|_| "ERROR: could not run file".to_string())?;
+    let mut interpreter = Interpreter::with_capabilities(Capabilities::all(), args);
+    interpreter.set_file(Path::new(path));
 
-    let mut parser = Parser::new(tokens);
-    let stmts = parser.parse()?;
-    interpreter.interpret(stmts)?;
+    let input = Box::new(io::BufReader::new(io::stdin()));
+    let debugger = debugger::Debugger::new(&source, input, Box::new(io::stdout()));
+    interpreter.set_hook(Box::new(debugger));
+    run(&mut interpreter, &source)
+}
 
-    Ok(())
+/// Runs the script under the profiler and prints its report to stderr. With
+/// `folded`, the call stacks are also written there for flamegraph tools.
+fn profile_file(path: &str, args: Vec<String>, folded: Option<&str>) -> Result<(), Failure> {
+    let source = fs::read_to_string(path).map_err(|_| "ERROR: could not run file".to_string())?;
+    let mut interpreter = Interpreter::with_capabilities(Capabilities::all(), args);
+    interpreter.set_file(Path::new(path));
+
+    let profile = Rc::new(RefCell::new(profiler::Profile::default()));
+    interpreter.set_hook(Box::new(profiler::Profiler(profile.clone())));
+    let result = run(&mut interpreter, &source);
+
+    let mut profile = profile.borrow_mut();
+    profile.finish();
+    eprint!("{}", profile.report());
+    if let Some(folded) = folded {
+        fs::write(folded, profile.folded())
+            .map_err(|err| format!("could not write '{}': {}", folded, err))?;
+    }
+    result
 }
 
-fn run_prompt() -> Result<(), String> {
-    let mut interpreter = Interpreter::new();
-    loop {
-        print!("> ");
-        match io::stdout().flush() {
-            Ok(_) => (),
-            Err(_) => return Err("ERROR: could not flush stdout".to_string()),
+/// Runs the script while recording coverage, then writes it to `lcov` and
+/// prints a summary to stderr.
+fn cover_file(path: &str, args: Vec<String>, lcov: &str) -> Result<(), Failure> {
+    let source = fs::read_to_string(path).map_err(|_| "ERROR: could not run file".to_string())?;
+    let mut interpreter = Interpreter::with_capabilities(Capabilities::all(), args);
+    interpreter.set_file(Path::new(path));
+
+    let tokens = Scanner::new(&source).scan_tokens()?;
+    let stmts = Parser::new(tokens).parse()?;
+    // Frames name their file the way the interpreter was given it.
+    let file = interpreter.frames()[0].file.clone();
+    let coverage = Rc::new(RefCell::new(coverage::Coverage::new(&file, &stmts)));
+    interpreter.set_hook(Box::new(coverage::Recorder(coverage.clone())));
+    let result = interpreter.interpret(stmts).map_err(Failure::from);
+
+    let coverage = coverage.borrow();
+    eprint!("{}", coverage.summary());
+    fs::write(lcov, coverage.lcov())
+        .map_err(|err| format!("could not write '{}': {}", lcov, err))?;
+    result
+}
+
+/// Headroom for parsing and walking deeply nested code. Lox calls grow the
+/// stack themselves when it runs low.
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+fn main() {
+    let cli = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run_cli)
+        .expect("could not start interpreter thread");
+
+    if cli.join().is_err() {
+        exit(70);
+    }
+}
+
+const USAGE: &str = "Usage: jlox [--tokens | --ast | --ast-json] [script [args...]]
+       jlox --no-optimize script [args...]
+       jlox --profile[=folded-file] script [args...]
+       jlox --coverage[=lcov-file] script [args...]
+       jlox fmt [--check] <path>...
+       jlox lint [--json] [--config file] <file>...
+       jlox test [path...]
+       jlox lsp
+       jlox debug script [args...]
+       jlox dap";
+
+/// What to do with the script named on the command line.
+enum Mode {
+    /// Run the script, optimized unless `--no-optimize` was given.
+    Run {
+        optimize: bool,
+    },
+    Tokens,
+    Ast,
+    AstJson,
+    /// Run under the profiler, maybe writing folded stacks to a file.
+    Profile(Option<String>),
+    /// Run recording coverage, written to the given LCOV file.
+    Coverage(String),
+}
+
+fn dump_file(path: &str, mode: Mode) -> Result<(), Failure> {
+    let source = fs::read_to_string(path).map_err(|_| "ERROR: could not read file".to_string())?;
+    let tokens = Scanner::new(&source).scan_tokens()?;
+
+    if let Mode::Tokens = mode {
+        for token in &tokens {
+            println!("{}:{} {}", token.line_number, token.column, token);
         }
+        return Ok(());
+    }
 
-        let mut buffer = String::new();
-        let stdin = io::stdin();
-        let mut handle = stdin.lock();
-        match handle.read_line(&mut buffer) {
-            Ok(line) => {
-                if line <= 1 {
-                    return Ok(());
-                }
+    let stmts = Parser::new(tokens).parse()?;
+    match mode {
+        Mode::AstJson => println!("{}", ast_json::to_json(&stmts)),
+        _ => {
+            for stmt in &stmts {
+                println!("{}", stmt);
             }
-            Err(_) => return Err("ERROR: could not read line".to_string()),
         }
+    }
 
-        match run(&mut interpreter, &buffer) {
-            Ok(_) => (),
-            Err(msg) => println!("{}", msg),
+    Ok(())
+}
+
+fn run_cli() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let (mode, args) = match args.first().map(String::as_str) {
+        Some("fmt") if args.len() > 1 => finish(formatter::run(&args[1..])),
+        Some("lint") if args.len() > 1 => finish(lint::run(&args[1..])),
+        Some("test") => finish(testing::run(&args[1..])),
+        Some("lsp") if args.len() == 1 => finish(lsp::run()),
+        Some("dap") if args.len() == 1 => finish(dap::run()),
+        Some("debug") if args.len() > 1 => finish(debug_file(&args[1], args[2..].to_vec())),
+        Some("--no-optimize") => (Mode::Run { optimize: false }, &args[1..]),
+        Some("--tokens") => (Mode::Tokens, &args[1..]),
+        Some("--ast") => (Mode::Ast, &args[1..]),
+        Some("--ast-json") => (Mode::AstJson, &args[1..]),
+        Some("--profile") => (Mode::Profile(None), &args[1..]),
+        Some(flag) if flag.starts_with("--profile=") => {
+            let folded = flag["--profile=".len()..].to_string();
+            (Mode::Profile(Some(folded)), &args[1..])
         }
-    }
+        Some("--coverage") => (Mode::Coverage("lcov.info".to_string()), &args[1..]),
+        Some(flag) if flag.starts_with("--coverage=") => {
+            let lcov = flag["--coverage=".len()..].to_string();
+            (Mode::Coverage(lcov), &args[1..])
+        }
+        Some(flag) if flag.starts_with("--") => {
+            eprintln!("{}", USAGE);
+            exit(64);
+        }
+        _ => (Mode::Run { optimize: true }, &args[..]),
+    };
+
+    let result = match (mode, args.first()) {
+        (Mode::Run { optimize }, Some(path)) => run_file(path, args[1..].to_vec(), optimize),
+        (Mode::Run { .. }, None) => repl::run_prompt(),
+        (Mode::Profile(folded), Some(path)) => {
+            profile_file(path, args[1..].to_vec(), folded.as_deref())
+        }
+        (Mode::Coverage(lcov), Some(path)) => cover_file(path, args[1..].to_vec(), &lcov),
+        (mode, Some(path)) if args.len() == 1 => dump_file(path, mode),
+        _ => {
+            eprintln!("{}", USAGE);
+            exit(64);
+        }
+    };
+
+    finish(result)
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() > 2 {
-        eprintln!("Usage: jlox [script]");
-        exit(64);
-    } else if args.len() == 2 {
-        match run_file(&args[1]) {
-            Ok(_) => exit(0),
-            Err(err) => {
-                eprintln!("ERROR:\n{}", err);
-                exit(1);
-            }
+/// Exits with the script's own status if it called `exit`, once whatever
+/// reports on the run has been written.
+fn finish(result: Result<(), impl Into<Failure>>) -> ! {
+    match result.map_err(Into::into) {
+        Ok(_) => exit(0),
+        Err(Failure::Exit(status)) => {
+            let _ = io::stdout().flush();
+            exit(status);
         }
-    } else {
-        match run_prompt() {
-            Ok(_) => exit(0),
-            Err(err) => {
-                eprintln!("ERROR:\n{}", err);
-                exit(1);
-            }
+        Err(Failure::Error(err)) => {
+            eprintln!("ERROR:\n{}", err);
+            exit(1);
         }
     }
 }
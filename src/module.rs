@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::expr::LiteralValue;
+
+/// The top-level definitions of an imported file, exposed as a namespace.
+pub struct Module {
+    pub path: PathBuf,
+    pub members: HashMap<String, LiteralValue>,
+}
+
+impl Module {
+    pub fn get(&self, name: &str) -> Option<&LiteralValue> {
+        self.members.get(name)
+    }
+}
+
+impl fmt::Debug for Module {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<module {}>", self.path.display())
+    }
+}
+
+impl PartialEq for Module {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// Modules shared by every interpreter taking part in one run, keyed by
+/// canonical path so each file is executed at most once.
+#[derive(Default)]
+pub struct ModuleCache {
+    pub loaded: HashMap<PathBuf, Rc<Module>>,
+    /// Modules whose top level is still executing, outermost first.
+    pub loading: Vec<PathBuf>,
+}
+
+impl ModuleCache {
+    pub fn cycle_error(&self, path: &PathBuf) -> Option<String> {
+        let start = self.loading.iter().position(|p| p == path)?;
+        let chain: Vec<String> = self.loading[start..]
+            .iter()
+            .chain(std::iter::once(path))
+            .map(|p| p.display().to_string())
+            .collect();
+
+        Some(format!("Import cycle detected: {}", chain.join(" -> ")))
+    }
+}
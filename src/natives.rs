@@ -0,0 +1,410 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use serde::Serialize;
+use serde_json::ser::PrettyFormatter;
+
+use crate::collection::{List, Map};
+use crate::environment::Environment;
+use crate::expr::LiteralValue;
+use crate::module::Module;
+
+type NativeFn = dyn Fn(&[LiteralValue]) -> Result<LiteralValue, NativeError>;
+
+/// Why a native function returned without a value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NativeError {
+    /// Raised in the script as an `Error` with this message.
+    Message(String),
+    /// The script called `exit` with this status.
+    Exit(i32),
+}
+
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: usize,
+    pub fun: Rc<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new<F>(name: &str, arity: usize, fun: F) -> Self
+    where
+        F: Fn(&[LiteralValue]) -> Result<LiteralValue, String> + 'static,
+    {
+        Self::with_errors(name, arity, move |arguments| {
+            fun(arguments).map_err(NativeError::Message)
+        })
+    }
+
+    /// Like `new`, for a function that can also stop the script.
+    pub fn with_errors<F>(name: &str, arity: usize, fun: F) -> Self
+    where
+        F: Fn(&[LiteralValue]) -> Result<LiteralValue, NativeError> + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            arity,
+            fun: Rc::new(fun),
+        }
+    }
+
+    pub fn call(&self, arguments: &[LiteralValue]) -> Result<LiteralValue, NativeError> {
+        (self.fun)(arguments)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.fun, &other.fun)
+    }
+}
+
+/// What a script is allowed to touch outside of the interpreter.
+#[derive(Debug, Clone, Copy)]
+pub struct Capabilities {
+    /// Files, imports, stdin, environment variables, script arguments and
+    /// `exit`.
+    pub io: bool,
+}
+
+impl Capabilities {
+    pub fn all() -> Self {
+        Self { io: true }
+    }
+}
+
+pub fn define_natives(
+    environment: &mut Environment,
+    capabilities: Capabilities,
+    args: Vec<String>,
+) {
+    define_core(environment);
+    if capabilities.io {
+        define_io(environment, args);
+    }
+}
+
+fn define(environment: &mut Environment, native: NativeFunction) {
+    environment.define(native.name.clone(), LiteralValue::Callable(native));
+}
+
+/// Natives that touch nothing outside the interpreter, always available.
+fn define_core(environment: &mut Environment) {
+    define(
+        environment,
+        NativeFunction::new("assert", 2, |arguments| {
+            if arguments[0].is_truthy() {
+                Ok(LiteralValue::Nil)
+            } else {
+                Err(format!("Assertion failed: {}", arguments[1]))
+            }
+        }),
+    );
+
+    define_collections(environment);
+
+    let json = [
+        NativeFunction::new("json.parse", 1, |arguments| {
+            let text = expect_string("json.parse", &arguments[0])?;
+            serde_json::from_str(text).map_err(|err| format!("json.parse: {}", err))
+        }),
+        NativeFunction::new("json.stringify", 2, |arguments| {
+            stringify(&arguments[0], &arguments[1])
+                .map_err(|err| format!("json.stringify: {}", err))
+        }),
+    ];
+    environment.define(
+        "json".to_string(),
+        LiteralValue::Namespace(Rc::new(Module {
+            path: PathBuf::from("<json>"),
+            members: json
+                .into_iter()
+                .map(|native| {
+                    let name = native.name.trim_start_matches("json.").to_string();
+                    (name, LiteralValue::Callable(native))
+                })
+                .collect(),
+        })),
+    );
+}
+
+/// Lists and maps, and the natives that read and change them.
+fn define_collections(environment: &mut Environment) {
+    define(
+        environment,
+        NativeFunction::new("list", 0, |_| Ok(LiteralValue::List(List::default()))),
+    );
+
+    define(
+        environment,
+        NativeFunction::new("map", 0, |_| Ok(LiteralValue::Map(Map::default()))),
+    );
+
+    define(
+        environment,
+        NativeFunction::new("len", 1, |arguments| {
+            let length = match &arguments[0] {
+                LiteralValue::List(list) => list.items().len(),
+                LiteralValue::Map(map) => map.entries().len(),
+                LiteralValue::StringValue(s) => s.chars().count(),
+                other => {
+                    return Err(format!(
+                        "len: expected a List, Map or String but got {}",
+                        other.to_type()
+                    ))
+                }
+            };
+            Ok(LiteralValue::Number(length as f32))
+        }),
+    );
+
+    define(
+        environment,
+        NativeFunction::new("push", 2, |arguments| {
+            let list = expect_list("push", &arguments[0])?;
+            list.items_mut().push(arguments[1].clone());
+            Ok(LiteralValue::Nil)
+        }),
+    );
+
+    // Missing map keys read as nil, so scripts can probe for optional ones.
+    define(
+        environment,
+        NativeFunction::new("get", 2, |arguments| match &arguments[0] {
+            LiteralValue::List(list) => {
+                let index = expect_index("get", &arguments[1])?;
+                match list.items().get(index) {
+                    Some(item) => Ok(item.clone()),
+                    None => Err(format!("get: index {} is out of bounds", index)),
+                }
+            }
+            LiteralValue::Map(map) => {
+                let key = expect_string("get", &arguments[1])?;
+                Ok(map.entries().get(key).cloned().unwrap_or(LiteralValue::Nil))
+            }
+            other => Err(format!(
+                "get: expected a List or Map but got {}",
+                other.to_type()
+            )),
+        }),
+    );
+
+    define(
+        environment,
+        NativeFunction::new("set", 3, |arguments| match &arguments[0] {
+            LiteralValue::List(list) => {
+                let index = expect_index("set", &arguments[1])?;
+                match list.items_mut().get_mut(index) {
+                    Some(item) => *item = arguments[2].clone(),
+                    None => return Err(format!("set: index {} is out of bounds", index)),
+                }
+                Ok(LiteralValue::Nil)
+            }
+            LiteralValue::Map(map) => {
+                let key = expect_string("set", &arguments[1])?;
+                map.entries_mut()
+                    .insert(key.to_string(), arguments[2].clone());
+                Ok(LiteralValue::Nil)
+            }
+            other => Err(format!(
+                "set: expected a List or Map but got {}",
+                other.to_type()
+            )),
+        }),
+    );
+
+    define(
+        environment,
+        NativeFunction::new("keys", 1, |arguments| match &arguments[0] {
+            LiteralValue::Map(map) => Ok(LiteralValue::List(List::new(
+                map.entries()
+                    .keys()
+                    .map(|key| LiteralValue::StringValue(key.as_str().into()))
+                    .collect(),
+            ))),
+            other => Err(format!("keys: expected a Map but got {}", other.to_type())),
+        }),
+    );
+}
+
+/// The most spaces `json.stringify` indents by, as in JavaScript.
+const MAX_INDENT: f32 = 10.0;
+
+/// `value` as JSON text, on one line when `indent` is nil, or one member per
+/// line indented by that many spaces, or by that string.
+fn stringify(value: &LiteralValue, indent: &LiteralValue) -> Result<LiteralValue, String> {
+    let indent = match indent {
+        LiteralValue::Nil => None,
+        LiteralValue::Number(n) if (0.0..=MAX_INDENT).contains(n) && n.fract() == 0.0 => {
+            Some(" ".repeat(*n as usize))
+        }
+        LiteralValue::Number(_) => {
+            return Err(format!(
+                "expected a whole number of spaces from 0 to {} as the indent but got {}",
+                MAX_INDENT, indent
+            ))
+        }
+        LiteralValue::StringValue(s) => Some(s.to_string()),
+        other => {
+            return Err(format!(
+                "expected nil, a number or a String as the indent but got {}",
+                other.to_type()
+            ))
+        }
+    };
+
+    let mut out = vec![];
+    let result = match &indent {
+        None => value.serialize(&mut serde_json::Serializer::new(&mut out)),
+        Some(indent) => {
+            let formatter = PrettyFormatter::with_indent(indent.as_bytes());
+            value.serialize(&mut serde_json::Serializer::with_formatter(
+                &mut out, formatter,
+            ))
+        }
+    };
+    result.map_err(|err| err.to_string())?;
+
+    Ok(LiteralValue::StringValue(
+        String::from_utf8_lossy(&out).as_ref().into(),
+    ))
+}
+
+fn define_io(environment: &mut Environment, args: Vec<String>) {
+    define(
+        environment,
+        NativeFunction::new("readFile", 1, |arguments| {
+            let path = expect_string("readFile", &arguments[0])?;
+            match fs::read_to_string(path) {
+                Ok(contents) => Ok(LiteralValue::StringValue(contents.into())),
+                Err(err) => Err(format!("readFile: could not read '{}': {}", path, err)),
+            }
+        }),
+    );
+
+    define(
+        environment,
+        NativeFunction::new("writeFile", 2, |arguments| {
+            let path = expect_string("writeFile", &arguments[0])?;
+            match fs::write(path, arguments[1].to_string()) {
+                Ok(_) => Ok(LiteralValue::Nil),
+                Err(err) => Err(format!("writeFile: could not write '{}': {}", path, err)),
+            }
+        }),
+    );
+
+    define(
+        environment,
+        NativeFunction::new("appendFile", 2, |arguments| {
+            let path = expect_string("appendFile", &arguments[0])?;
+            let result = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(arguments[1].to_string().as_bytes()));
+            match result {
+                Ok(_) => Ok(LiteralValue::Nil),
+                Err(err) => Err(format!("appendFile: could not write '{}': {}", path, err)),
+            }
+        }),
+    );
+
+    define(
+        environment,
+        NativeFunction::new("readLine", 0, |_| {
+            let mut buffer = String::new();
+            match io::stdin().lock().read_line(&mut buffer) {
+                Ok(0) => Ok(LiteralValue::Nil),
+                Ok(_) => {
+                    let line = buffer.trim_end_matches(['\n', '\r']);
+                    Ok(LiteralValue::StringValue(line.into()))
+                }
+                Err(err) => Err(format!("readLine: could not read stdin: {}", err)),
+            }
+        }),
+    );
+
+    let count = args.len();
+    define(
+        environment,
+        NativeFunction::new("argCount", 0, move |_| {
+            Ok(LiteralValue::Number(count as f32))
+        }),
+    );
+
+    define(
+        environment,
+        NativeFunction::new("arg", 1, move |arguments| {
+            let index = expect_index("arg", &arguments[0])?;
+            match args.get(index) {
+                Some(arg) => Ok(LiteralValue::StringValue(arg.as_str().into())),
+                None => Ok(LiteralValue::Nil),
+            }
+        }),
+    );
+
+    define(
+        environment,
+        NativeFunction::new("getEnv", 1, |arguments| {
+            let name = expect_string("getEnv", &arguments[0])?;
+            match env::var(name) {
+                Ok(value) => Ok(LiteralValue::StringValue(value.into())),
+                Err(_) => Ok(LiteralValue::Nil),
+            }
+        }),
+    );
+
+    define(
+        environment,
+        NativeFunction::with_errors("exit", 1, |arguments| match &arguments[0] {
+            LiteralValue::Number(x) if x.fract() == 0.0 => Err(NativeError::Exit(*x as i32)),
+            other => Err(NativeError::Message(format!(
+                "exit: expected an integer but got {}",
+                other.to_type()
+            ))),
+        }),
+    );
+}
+
+fn expect_string<'a>(name: &str, value: &'a LiteralValue) -> Result<&'a str, String> {
+    match value {
+        LiteralValue::StringValue(s) => Ok(s),
+        other => Err(format!(
+            "{}: expected a String but got {}",
+            name,
+            other.to_type()
+        )),
+    }
+}
+
+fn expect_list<'a>(name: &str, value: &'a LiteralValue) -> Result<&'a List, String> {
+    match value {
+        LiteralValue::List(list) => Ok(list),
+        other => Err(format!(
+            "{}: expected a List but got {}",
+            name,
+            other.to_type()
+        )),
+    }
+}
+
+fn expect_index(name: &str, value: &LiteralValue) -> Result<usize, String> {
+    match value {
+        LiteralValue::Number(x) if *x >= 0.0 && x.fract() == 0.0 => Ok(*x as usize),
+        other => Err(format!(
+            "{}: expected a non-negative integer but got {}",
+            name, other
+        )),
+    }
+}
@@ -0,0 +1,341 @@
+use std::rc::Rc;
+
+use crate::expr::{Expr, LiteralValue};
+use crate::interpreter::Interpreter;
+use crate::limits;
+use crate::natives::Capabilities;
+use crate::scanner::TokenType;
+use crate::stmt::Stmt;
+
+/// Rewrites `stmts` to do less work at run time without changing what they
+/// do: operations on constants are computed ahead of time, branches that
+/// can never run are dropped and `!!` chains are shortened.
+///
+/// Operations that would fail, such as `1 / 0` or `"a" - 1`, are left in
+/// place to fail when and where they would have.
+pub fn optimize(stmts: Vec<Stmt>) -> Vec<Stmt> {
+    Optimizer::new().stmts(stmts)
+}
+
+struct Optimizer {
+    /// Evaluates folded operations, so they behave exactly as they would
+    /// have at run time.
+    interpreter: Interpreter,
+}
+
+impl Optimizer {
+    fn new() -> Self {
+        Self {
+            interpreter: Interpreter::with_capabilities(Capabilities { io: false }, vec![]),
+        }
+    }
+
+    fn stmts(&mut self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+        stmts
+            .into_iter()
+            .filter_map(|stmt| self.stmt(stmt))
+            .collect()
+    }
+
+    /// A statement in a branch or loop body, where one has to remain.
+    fn body(&mut self, stmt: Stmt) -> Stmt {
+        self.stmt(stmt)
+            .unwrap_or(Stmt::Block { statements: vec![] })
+    }
+
+    /// The optimized statement, or nothing if it would never do anything.
+    fn stmt(&mut self, stmt: Stmt) -> Option<Stmt> {
+        let stmt = match stmt {
+            Stmt::Block { statements } => Stmt::Block {
+                statements: self.stmts(statements),
+            },
+            Stmt::Expression { expression } => Stmt::Expression {
+                expression: self.expr(expression),
+            },
+            Stmt::Function { name, params, body } => Stmt::Function {
+                name,
+                params,
+                // Bodies are only shared once the function has been declared.
+                body: match Rc::try_unwrap(body) {
+                    Ok(body) => Rc::new(self.stmts(body)),
+                    Err(body) => body,
+                },
+            },
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let condition = self.expr(condition);
+                match constant(&condition) {
+                    Some(value) if value.is_truthy() => return self.stmt(*then_branch),
+                    Some(_) => return else_branch.and_then(|branch| self.stmt(*branch)),
+                    None => Stmt::If {
+                        condition,
+                        then_branch: Box::new(self.body(*then_branch)),
+                        else_branch: else_branch.map(|branch| Box::new(self.body(*branch))),
+                    },
+                }
+            }
+            Stmt::Print { expression } => Stmt::Print {
+                expression: self.expr(expression),
+            },
+            Stmt::Return { keyword, value } => Stmt::Return {
+                keyword,
+                value: self.expr(value),
+            },
+            Stmt::Test { name, body } => Stmt::Test {
+                name,
+                body: self.stmts(body),
+            },
+            Stmt::Throw { keyword, value } => Stmt::Throw {
+                keyword,
+                value: self.expr(value),
+            },
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => Stmt::Try {
+                body: self.stmts(body),
+                catch: catch.map(|(name, handler)| (name, self.stmts(handler))),
+                finally: finally.map(|finally| self.stmts(finally)),
+            },
+            Stmt::Var { name, initializer } => Stmt::Var {
+                name,
+                initializer: self.expr(initializer),
+            },
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => {
+                let condition = self.expr(condition);
+                if constant(&condition).is_some_and(|value| !value.is_truthy()) {
+                    return None;
+                }
+                Stmt::While {
+                    condition,
+                    body: Box::new(self.body(*body)),
+                    increment: increment.map(|increment| self.expr(increment)),
+                }
+            }
+            stmt @ (Stmt::Break { .. } | Stmt::Continue { .. } | Stmt::Import { .. }) => stmt,
+        };
+
+        Some(stmt)
+    }
+
+    fn expr(&mut self, expr: Expr) -> Expr {
+        limits::with_stack(|| self.optimize_expr(expr))
+    }
+
+    fn optimize_expr(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Assign {
+                name,
+                value,
+                location,
+            } => Expr::Assign {
+                name,
+                value: Box::new(self.expr(*value)),
+                location,
+            },
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let binary = Expr::Binary {
+                    left: Box::new(self.expr(*left)),
+                    operator,
+                    right: Box::new(self.expr(*right)),
+                };
+                self.fold(binary)
+            }
+            Expr::Call {
+                callee,
+                paren,
+                arguments,
+            } => Expr::Call {
+                callee: Box::new(self.expr(*callee)),
+                paren,
+                arguments: arguments.into_iter().map(|arg| self.expr(arg)).collect(),
+            },
+            Expr::Get { object, name } => Expr::Get {
+                object: Box::new(self.expr(*object)),
+                name,
+            },
+            // Grouping only matters to the parser.
+            Expr::Grouping { expression } => self.expr(*expression),
+            Expr::Unary { operator, right } => {
+                let right = self.expr(*right);
+                match (operator.token_type, right) {
+                    // `!!e` is `e` when `e` is already true or false.
+                    (
+                        TokenType::Bang,
+                        Expr::Unary {
+                            operator: inner,
+                            right,
+                        },
+                    ) if inner.token_type == TokenType::Bang && is_boolean(&right) => *right,
+                    (_, right) => self.fold(Expr::Unary {
+                        operator,
+                        right: Box::new(right),
+                    }),
+                }
+            }
+            expr @ (Expr::Literal { .. } | Expr::Variable { .. }) => expr,
+        }
+    }
+
+    /// Computes an operation whose operands are all constants, unless doing
+    /// so fails.
+    fn fold(&mut self, expr: Expr) -> Expr {
+        let operands_constant = match &expr {
+            Expr::Binary { left, right, .. } => {
+                constant(left).is_some() && constant(right).is_some()
+            }
+            Expr::Unary { right, .. } => constant(right).is_some(),
+            _ => false,
+        };
+        if !operands_constant {
+            return expr;
+        }
+
+        match expr.evaluate(&mut self.interpreter) {
+            Ok(value) => Expr::Literal {
+                value,
+                line: expr.line(),
+            },
+            Err(_) => expr,
+        }
+    }
+}
+
+fn constant(expr: &Expr) -> Option<&LiteralValue> {
+    match expr {
+        Expr::Literal { value, .. } => Some(value),
+        _ => None,
+    }
+}
+
+/// Whether `expr` always evaluates to `true` or `false`.
+fn is_boolean(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal { value, .. } => matches!(value, LiteralValue::True | LiteralValue::False),
+        Expr::Unary { operator, .. } => operator.token_type == TokenType::Bang,
+        Expr::Binary { operator, .. } => matches!(
+            operator.token_type,
+            TokenType::BangEqual
+                | TokenType::EqualEqual
+                | TokenType::Greater
+                | TokenType::GreaterEqual
+                | TokenType::Less
+                | TokenType::LessEqual
+        ),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        Parser::new(Scanner::new(source).scan_tokens().unwrap())
+            .parse()
+            .unwrap()
+    }
+
+    fn optimized(source: &str) -> Vec<String> {
+        optimize(parse(source))
+            .iter()
+            .map(|stmt| stmt.to_string())
+            .collect()
+    }
+
+    /// What `source` prints, and the error it stops with and where.
+    fn outcome(stmts: Vec<Stmt>) -> (String, Option<(String, usize)>) {
+        let output = Rc::new(RefCell::new(vec![]));
+        let mut interpreter = Interpreter::with_capabilities(Capabilities { io: false }, vec![]);
+        interpreter.set_output(output.clone());
+
+        let error = interpreter
+            .interpret(stmts)
+            .err()
+            .map(|exception| (exception.message(), exception.line));
+        let output = String::from_utf8(output.borrow().clone()).unwrap();
+        (output, error)
+    }
+
+    #[test]
+    fn folds_constant_operations() {
+        assert_eq!(
+            optimized(
+                "var day = 60 * 60 * 24;\nprint \"a\" + \"b\" + day;\nprint -(2 - 3) < 2 == true;"
+            ),
+            [
+                "(var day 86400)",
+                "(print (+ ab (var day)))",
+                "(print true)"
+            ]
+        );
+    }
+
+    #[test]
+    fn drops_branches_that_never_run() {
+        assert_eq!(
+            optimized(
+                "if (1 > 2) print 1; else print 2;\nwhile (false) print 3;\n\
+                 if (nil) print 4;\nwhile (true) if (0) print 5;"
+            ),
+            ["(print 2)", "(while true (block))"]
+        );
+    }
+
+    #[test]
+    fn shortens_bang_chains() {
+        assert_eq!(
+            optimized("print !!(a < b);\nprint !!!a;\nprint !!a;\nprint !!!!a;"),
+            [
+                "(print (< (var a) (var b)))",
+                "(print (! (var a)))",
+                "(print (! (! (var a))))",
+                "(print (! (! (var a))))"
+            ]
+        );
+    }
+
+    #[test]
+    fn preserves_behavior() {
+        let programs = [
+            "print 60 * 60 * 24;\nprint \"con\" + \"cat\";\nprint 1 < 2;\nprint 0.1 + 0.2;",
+            "var a = 1;\nif (a > 0) print \"yes\"; else print \"no\";\nif (false) print \"no\";",
+            "print !!nil;\nprint !!0;\nprint !!\"\";\nprint !!!1;",
+            "print 1;\nprint 1 / 0;",
+            "print \"a\" +\n  \"b\" - 1;",
+            "print -\"text\";",
+            "var s = \"x\";\nprint !!(s < 1);",
+            "try {\n  print 2 * (3 + \"4\");\n} catch (e) {\n  print e.message;\n  print e.line;\n}",
+            "fun f(n) { if (true) return n * (2 + 2); }\nprint f(3);\nwhile (false) print f(1);",
+        ];
+
+        for program in programs {
+            assert_eq!(
+                outcome(optimize(parse(program))),
+                outcome(parse(program)),
+                "{}",
+                program
+            );
+        }
+        assert_eq!(
+            outcome(optimize(parse("print 1;\nprint 1 / 0;"))),
+            ("1\n".to_string(), Some(("Division by zero".to_string(), 2)))
+        );
+    }
+}
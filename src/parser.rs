@@ -1,19 +1,47 @@
-use core::panic;
+use std::mem;
+use std::rc::Rc;
 
 use crate::expr::{Expr, Expr::*, LiteralValue};
+use crate::resolver::Location;
 use crate::scanner::{Token, TokenType, TokenType::*};
-use crate::stmt::Stmt::{self, *};
+use crate::stmt::Stmt;
+
+/// How deeply statements and expressions may nest.
+const MAX_NESTING: usize = 128;
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    function_depth: usize,
+    loop_depth: usize,
+    nesting: usize,
+    repl: bool,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(mut tokens: Vec<Token>) -> Self {
+        // Everything below relies on the tokens ending with an Eof.
+        if tokens.last().map(|token| token.token_type) != Some(Eof) {
+            let line = tokens.last().map_or(1, |token| token.line_number);
+            tokens.push(Token::new(Eof, "", None, line, 0));
+        }
+
         Self {
-            tokens: tokens,
+            tokens,
             current: 0,
+            function_depth: 0,
+            loop_depth: 0,
+            nesting: 0,
+            repl: false,
+        }
+    }
+
+    /// A parser that also accepts a final expression without its ';', so
+    /// the REPL can evaluate `1 + 2` as typed.
+    pub fn for_repl(tokens: Vec<Token>) -> Self {
+        Self {
+            repl: true,
+            ..Self::new(tokens)
         }
     }
 
@@ -22,7 +50,11 @@ impl Parser {
         let mut errors = vec![];
 
         while !self.is_at_end() {
-            let stmt = self.declaration();
+            let stmt = if self.match_token(Test) {
+                self.test_declaration()
+            } else {
+                self.declaration()
+            };
             match stmt {
                 Ok(s) => stmts.push(s),
                 Err(msg) => {
@@ -39,29 +71,96 @@ impl Parser {
     }
 
     fn declaration(&mut self) -> Result<Stmt, String> {
-        if self.match_token(Var) {
-            match self.var_declaration() {
-                Ok(stmt) => return Ok(stmt),
-                Err(msg) => {
-                    self.synchronize();
-                    Err(msg)
-                }
-            }
+        let result = if self.match_token(Var) {
+            self.var_declaration()
+        } else if self.match_token(Import) {
+            self.import_declaration()
+        } else if self.match_token(Fun) {
+            self.function_declaration()
+        } else if self.match_token(Test) {
+            Err(Self::error(
+                &self.previous(),
+                "Tests can only be declared at the top level.",
+            ))
         } else {
             self.statement()
+        };
+
+        if result.is_err() {
+            self.synchronize();
         }
+        result
+    }
+
+    fn function_declaration(&mut self) -> Result<Stmt, String> {
+        let name = self.consume(Identifier, "Expect function name.")?;
+        self.consume(LeftParen, "Expect '(' after function name.")?;
+
+        let mut params = vec![];
+        if self.peek().token_type != RightParen {
+            loop {
+                if params.len() >= 255 {
+                    return Err("Can't have more than 255 parameters.".to_string());
+                }
+                params.push(self.consume(Identifier, "Expect parameter name.")?);
+
+                if !self.match_token(Comma) {
+                    break;
+                }
+            }
+        }
+        self.consume(RightParen, "Expect ')' after parameters.")?;
+        self.consume(LeftBrace, "Expect '{' before function body.")?;
+
+        // A loop around the declaration does not extend into the body.
+        let loop_depth = mem::replace(&mut self.loop_depth, 0);
+        self.function_depth += 1;
+        let body = self.block();
+        self.function_depth -= 1;
+        self.loop_depth = loop_depth;
+
+        Ok(Stmt::Function {
+            name,
+            params,
+            body: Rc::new(body?),
+        })
+    }
+
+    fn test_declaration(&mut self) -> Result<Stmt, String> {
+        let result = self.test_body();
+        if result.is_err() {
+            self.synchronize();
+        }
+        result
+    }
+
+    fn test_body(&mut self) -> Result<Stmt, String> {
+        let name = self.consume(StringLit, "Expect test name after 'test'.")?;
+        self.consume(LeftBrace, "Expect '{' before test body.")?;
+        let body = self.block()?;
+
+        Ok(Stmt::Test { name, body })
+    }
+
+    fn import_declaration(&mut self) -> Result<Stmt, String> {
+        let path = self.consume(StringLit, "Expect module path after 'import'.")?;
+        self.consume(As, "Expect 'as' after module path.")?;
+        let name = self.consume(Identifier, "Expect module name after 'as'.")?;
+        self.consume(Semicolon, "Expect ';' after import.")?;
+
+        Ok(Stmt::Import { path, name })
     }
 
     fn var_declaration(&mut self) -> Result<Stmt, String> {
         let token = self.consume(Identifier, "Expected variable name")?;
-        let initializer;
-        if self.match_token(Equal) {
-            initializer = self.expression()?;
+        let initializer = if self.match_token(Equal) {
+            self.expression()?
         } else {
-            initializer = Literal {
+            Literal {
                 value: LiteralValue::Nil,
-            };
-        }
+                line: token.line_number,
+            }
+        };
 
         self.consume(Semicolon, "Expected ';' after variable declaration")?;
 
@@ -72,11 +171,221 @@ impl Parser {
     }
 
     fn statement(&mut self) -> Result<Stmt, String> {
-        if self.match_token(Print) {
-            return self.print_statement();
+        self.nested(|p| {
+            if p.match_token(Print) {
+                return p.print_statement();
+            }
+            if p.match_token(LeftBrace) {
+                return Ok(Stmt::Block {
+                    statements: p.block()?,
+                });
+            }
+            if p.match_token(If) {
+                return p.if_statement();
+            }
+            if p.match_token(While) {
+                return p.while_statement();
+            }
+            if p.match_token(For) {
+                return p.for_statement();
+            }
+            if p.match_tokens(&[Break, Continue]) {
+                return p.loop_control_statement();
+            }
+            if p.match_token(Return) {
+                return p.return_statement();
+            }
+            if p.match_token(Throw) {
+                return p.throw_statement();
+            }
+            if p.match_token(Try) {
+                return p.try_statement();
+            }
+
+            p.expression_statement()
+        })
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, String> {
+        self.nested(|p| {
+            let mut statements = vec![];
+
+            while p.peek().token_type != RightBrace && !p.is_at_end() {
+                statements.push(p.declaration()?);
+            }
+
+            p.consume(RightBrace, "Expect '}' after block.")?;
+
+            Ok(statements)
+        })
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, String> {
+        self.consume(LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(RightParen, "Expect ')' after if condition.")?;
+
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.match_token(Else) {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+
+        Ok(Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    fn while_statement(&mut self) -> Result<Stmt, String> {
+        self.consume(LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(RightParen, "Expect ')' after condition.")?;
+        let body = self.loop_body()?;
+
+        Ok(Stmt::While {
+            condition,
+            body: Box::new(body),
+            increment: None,
+        })
+    }
+
+    fn for_statement(&mut self) -> Result<Stmt, String> {
+        let keyword = self.previous();
+        self.consume(LeftParen, "Expect '(' after 'for'.")?;
+
+        let initializer = if self.match_token(Semicolon) {
+            None
+        } else if self.match_token(Var) {
+            Some(self.var_declaration()?)
+        } else {
+            Some(self.expression_statement()?)
+        };
+
+        let condition = if self.peek().token_type == Semicolon {
+            Literal {
+                value: LiteralValue::True,
+                line: keyword.line_number,
+            }
+        } else {
+            self.expression()?
+        };
+        self.consume(Semicolon, "Expect ';' after loop condition.")?;
+
+        let increment = if self.peek().token_type == RightParen {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(RightParen, "Expect ')' after for clauses.")?;
+
+        let body = self.loop_body()?;
+        let mut statements = vec![];
+        if let Some(initializer) = initializer {
+            statements.push(initializer);
+        }
+        statements.push(Stmt::While {
+            condition,
+            body: Box::new(body),
+            increment,
+        });
+
+        Ok(Stmt::Block { statements })
+    }
+
+    fn loop_body(&mut self) -> Result<Stmt, String> {
+        self.loop_depth += 1;
+        let body = self.statement();
+        self.loop_depth -= 1;
+
+        body
+    }
+
+    fn loop_control_statement(&mut self) -> Result<Stmt, String> {
+        let keyword = self.previous();
+        if self.loop_depth == 0 {
+            return Err(format!(
+                "[line {}] Can't use '{}' outside of a loop.",
+                keyword.line_number, keyword.lexeme
+            ));
+        }
+        self.consume(
+            Semicolon,
+            &format!("Expect ';' after '{}'.", keyword.lexeme),
+        )?;
+
+        if keyword.token_type == Break {
+            Ok(Stmt::Break { keyword })
+        } else {
+            Ok(Stmt::Continue { keyword })
+        }
+    }
+
+    fn return_statement(&mut self) -> Result<Stmt, String> {
+        let keyword = self.previous();
+        if self.function_depth == 0 {
+            return Err(format!(
+                "[line {}] Can't return from top-level code.",
+                keyword.line_number
+            ));
+        }
+
+        let value = if self.peek().token_type == Semicolon {
+            Literal {
+                value: LiteralValue::Nil,
+                line: keyword.line_number,
+            }
+        } else {
+            self.expression()?
+        };
+        self.consume(Semicolon, "Expect ';' after return value.")?;
+
+        Ok(Stmt::Return { keyword, value })
+    }
+
+    fn throw_statement(&mut self) -> Result<Stmt, String> {
+        let keyword = self.previous();
+        let value = self.expression()?;
+        self.consume(Semicolon, "Expect ';' after thrown value.")?;
+
+        Ok(Stmt::Throw { keyword, value })
+    }
+
+    fn try_statement(&mut self) -> Result<Stmt, String> {
+        self.consume(LeftBrace, "Expect '{' after 'try'.")?;
+        let body = self.block()?;
+
+        let catch = if self.match_token(Catch) {
+            self.consume(LeftParen, "Expect '(' after 'catch'.")?;
+            let name = self.consume(Identifier, "Expect exception variable name.")?;
+            self.consume(RightParen, "Expect ')' after exception variable.")?;
+            self.consume(LeftBrace, "Expect '{' before catch body.")?;
+            Some((name, self.block()?))
+        } else {
+            None
+        };
+
+        let finally = if self.match_token(Finally) {
+            self.consume(LeftBrace, "Expect '{' after 'finally'.")?;
+            Some(self.block()?)
+        } else {
+            None
+        };
+
+        if catch.is_none() && finally.is_none() {
+            return Err(Self::error(
+                &self.previous(),
+                "Expect 'catch' or 'finally' after try block.",
+            ));
         }
 
-        self.expression_statement()
+        Ok(Stmt::Try {
+            body,
+            catch,
+            finally,
+        })
     }
 
     fn print_statement(&mut self) -> Result<Stmt, String> {
@@ -88,26 +397,28 @@ impl Parser {
 
     fn expression_statement(&mut self) -> Result<Stmt, String> {
         let expr = self.expression()?;
-        self.consume(Semicolon, "Expect ';' after expression.")?;
+        if !(self.repl && self.is_at_end()) {
+            self.consume(Semicolon, "Expect ';' after expression.")?;
+        }
 
         Ok(Stmt::Expression { expression: expr })
     }
 
     fn expression(&mut self) -> Result<Expr, String> {
-        self.assignment()
+        self.nested(Self::assignment)
     }
 
     fn assignment(&mut self) -> Result<Expr, String> {
-        let mut expr = self.equality()?;
+        let expr = self.equality()?;
 
         if self.match_token(Equal) {
-            let equals = self.previous();
-            let value = self.assignment()?;
+            let value = self.nested(Self::assignment)?;
 
             match expr {
-                Variable { name } => Ok(Assign {
+                Variable { name, .. } => Ok(Assign {
                     name,
                     value: Box::from(value),
+                    location: Location::Unresolved,
                 }),
                 _ => Err("Invalid assignment target.".to_string()),
             }
@@ -117,60 +428,35 @@ impl Parser {
     }
 
     fn equality(&mut self) -> Result<Expr, String> {
-        let mut expr = self.comparison()?;
-        while self.match_tokens(&[BangEqual, EqualEqual]) {
-            let operator = self.previous();
-            let rhs = self.comparison()?;
-            expr = Binary {
-                left: Box::from(expr),
-                operator: operator,
-                right: Box::from(rhs),
-            };
-        }
-
-        Ok(expr)
+        self.binary(&[BangEqual, EqualEqual], Self::comparison)
     }
 
     fn comparison(&mut self) -> Result<Expr, String> {
-        let mut expr = self.term()?;
-
-        while self.match_tokens(&[Greater, GreaterEqual, Less, LessEqual]) {
-            let op = self.previous();
-            let rhs = self.term()?;
-            expr = Binary {
-                left: Box::from(expr),
-                operator: op,
-                right: Box::from(rhs),
-            };
-        }
-
-        Ok(expr)
+        self.binary(&[Greater, GreaterEqual, Less, LessEqual], Self::term)
     }
 
     fn term(&mut self) -> Result<Expr, String> {
-        let mut expr = self.factor()?;
-
-        while self.match_tokens(&[Minus, Plus]) {
-            let op = self.previous();
-            let rhs = self.factor()?;
-            expr = Binary {
-                left: Box::from(expr),
-                operator: op,
-                right: Box::from(rhs),
-            };
-        }
-
-        Ok(expr)
+        self.binary(&[Minus, Plus], Self::factor)
     }
 
     fn factor(&mut self) -> Result<Expr, String> {
-        let mut expr = self.unary()?;
-        while self.match_tokens(&[Slash, Star]) {
-            let op = self.previous();
-            let rhs = self.unary()?;
+        self.binary(&[Slash, Star], Self::unary)
+    }
+
+    /// A left-associative chain of `operand`s joined by `operators`. The
+    /// chain is parsed in a loop, so only its operands count as nesting.
+    fn binary(
+        &mut self,
+        operators: &[TokenType],
+        operand: fn(&mut Self) -> Result<Expr, String>,
+    ) -> Result<Expr, String> {
+        let mut expr = operand(self)?;
+        while self.match_tokens(operators) {
+            let operator = self.previous();
+            let rhs = operand(self)?;
             expr = Binary {
                 left: Box::from(expr),
-                operator: op,
+                operator,
                 right: Box::from(rhs),
             };
         }
@@ -181,46 +467,114 @@ impl Parser {
     fn unary(&mut self) -> Result<Expr, String> {
         if self.match_tokens(&[Bang, Minus]) {
             let op = self.previous();
-            let rhs = self.unary()?;
+            let rhs = self.nested(Self::unary)?;
             Ok(Unary {
                 operator: op,
                 right: Box::from(rhs),
             })
         } else {
-            self.primary()
+            self.call()
         }
     }
 
+    fn call(&mut self) -> Result<Expr, String> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.match_token(LeftParen) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_token(Dot) {
+                let name = self.consume(Identifier, "Expect property name after '.'.")?;
+                expr = Get {
+                    object: Box::from(expr),
+                    name,
+                };
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr, String> {
+        let mut arguments = vec![];
+
+        if self.peek().token_type != RightParen {
+            loop {
+                if arguments.len() >= 255 {
+                    return Err("Can't have more than 255 arguments.".to_string());
+                }
+                arguments.push(self.expression()?);
+
+                if !self.match_token(Comma) {
+                    break;
+                }
+            }
+        }
+
+        let paren = self.consume(RightParen, "Expect ')' after arguments.")?;
+
+        Ok(Call {
+            callee: Box::from(callee),
+            paren,
+            arguments,
+        })
+    }
+
     fn primary(&mut self) -> Result<Expr, String> {
         let token = self.peek();
-        let result;
-        match token.token_type {
+        let result = match token.token_type {
             LeftParen => {
                 self.advance();
                 let expr = self.expression()?;
                 self.consume(RightParen, "Expect ')' after expression.")?;
-                result = Grouping {
+                Grouping {
                     expression: Box::from(expr),
                 }
             }
             False | True | Nil | Number | StringLit => {
                 self.advance();
-                result = Literal {
-                    value: LiteralValue::from_token(token),
-                };
+                Literal {
+                    line: token.line_number,
+                    value: LiteralValue::from_token(token.clone())
+                        .map_err(|msg| Self::error(&token, &msg))?,
+                }
             }
             Identifier => {
                 self.advance();
-                result = Variable {
+                Variable {
                     name: self.previous(),
+                    location: Location::Unresolved,
                 }
             }
-            _ => return Err("Expected expression".to_string()),
-        }
+            _ => return Err(Self::error(&token, "Expected expression")),
+        };
 
         Ok(result)
     }
 
+    /// Runs `parse` one level deeper, failing instead once input nests so
+    /// deeply that parsing or running it could overflow the stack.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, String>,
+    ) -> Result<T, String> {
+        self.deepen()?;
+        let result = parse(self);
+        self.nesting -= 1;
+        result
+    }
+
+    /// Goes one level deeper, unless that is too deep.
+    fn deepen(&mut self) -> Result<(), String> {
+        if self.nesting >= MAX_NESTING {
+            return Err(Self::error(&self.peek(), "Too much nesting."));
+        }
+        self.nesting += 1;
+        Ok(())
+    }
+
     fn consume(&mut self, token_type: TokenType, msg: &str) -> Result<Token, String> {
         let token = self.peek();
         if token.token_type == token_type {
@@ -228,17 +582,13 @@ impl Parser {
             let token = self.previous();
             Ok(token)
         } else {
-            Err(msg.to_string())
+            Err(Self::error(&token, msg))
         }
     }
 
-    //   fn error(&mut self, token: Token, msg: &str) -> Result<(), String> {
-    //	if token.token_type == Eof {
-    //	    Err(format!("{} at end {}", token.line_number.to_string(), msg.to_string()))
-    //	} else {
-    //	    Err(format!("{} at '{}' {}", token.line_number.to_string(), token.lexeme, msg.to_string()))
-    //	}
-    //    }
+    fn error(token: &Token, msg: &str) -> String {
+        format!("[line {}] {}", token.line_number, msg)
+    }
 
     fn match_token(&mut self, typ: TokenType) -> bool {
         if self.is_at_end() {
@@ -276,7 +626,7 @@ impl Parser {
     }
 
     fn previous(&mut self) -> Token {
-        self.tokens[self.current - 1].clone()
+        self.tokens[self.current.saturating_sub(1)].clone()
     }
 
     fn is_at_end(&mut self) -> bool {
@@ -292,7 +642,8 @@ impl Parser {
             }
 
             match self.peek().token_type {
-                Class | Fun | Var | For | If | While | Print | Return => return,
+                Class | Fun | Var | For | If | While | Print | Return | Import | Throw | Try
+                | Break | Continue | Test => return,
                 _ => (),
             }
 
@@ -310,33 +661,38 @@ mod tests {
     fn test_addition() {
         let one = Token {
             token_type: Number,
-            lexeme: "1".to_string(),
+            lexeme: "1".into(),
             literal: Some(FloatValue(1.0)),
             line_number: 0,
+            column: 0,
         };
         let plus = Token {
             token_type: Plus,
-            lexeme: "+".to_string(),
+            lexeme: "+".into(),
             literal: None,
             line_number: 0,
+            column: 0,
         };
         let two = Token {
             token_type: Number,
-            lexeme: "2".to_string(),
+            lexeme: "2".into(),
             literal: Some(FloatValue(2.0)),
             line_number: 0,
+            column: 0,
         };
         let semicol = Token {
             token_type: Semicolon,
-            lexeme: ";".to_string(),
+            lexeme: ";".into(),
             literal: None,
             line_number: 0,
+            column: 0,
         };
         let eof = Token {
             token_type: Eof,
-            lexeme: "".to_string(),
+            lexeme: "".into(),
             literal: None,
             line_number: 0,
+            column: 0,
         };
 
         let tokens = vec![one, plus, two, semicol, eof];
@@ -374,4 +730,126 @@ mod tests {
 
         assert_eq!(string_expr, "(== 1 (group (+ 5 7)))");
     }
+
+    #[test]
+    fn test_recovers_from_statement_errors() {
+        let source = "print ; { print 1 } print 2;";
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let errors = parser.parse().err().unwrap();
+
+        assert_eq!(
+            errors,
+            "[line 1] Expected expression\n[line 1] Expect ';' after value."
+        );
+    }
+
+    #[test]
+    fn test_rejects_loop_control_outside_loops() {
+        let parse = |source: &str| {
+            let tokens = Scanner::new(source).scan_tokens().unwrap();
+            Parser::new(tokens).parse().err()
+        };
+
+        assert_eq!(
+            parse("break;"),
+            Some("[line 1] Can't use 'break' outside of a loop.".to_string())
+        );
+        assert_eq!(
+            parse("while (true) { fun f() { continue; } }").map(|e| e
+                .lines()
+                .next()
+                .unwrap()
+                .to_string()),
+            Some("[line 1] Can't use 'continue' outside of a loop.".to_string())
+        );
+        assert_eq!(
+            parse("for (;;) { if (true) break; else { continue; } }"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_bare_expressions_only_in_repl() {
+        let tokens = Scanner::new("var a = 1; a + 2").scan_tokens().unwrap();
+
+        assert_eq!(
+            Parser::new(tokens.clone()).parse().err(),
+            Some("[line 1] Expect ';' after expression.".to_string())
+        );
+        assert_eq!(Parser::for_repl(tokens).parse().map(|s| s.len()), Ok(2));
+
+        let tokens = Scanner::new("{ a + 2 }").scan_tokens().unwrap();
+        assert!(Parser::for_repl(tokens).parse().is_err());
+    }
+
+    #[test]
+    fn test_test_blocks_only_at_top_level() {
+        let parse = |source: &str| {
+            let tokens = Scanner::new(source).scan_tokens().unwrap();
+            Parser::new(tokens).parse()
+        };
+
+        let stmts = parse("test \"adds\" { print 1 + 1; }").unwrap();
+        assert_eq!(stmts[0].to_string(), "(test \"adds\" (print (+ 1 1)))");
+        assert_eq!(
+            parse("fun f() { test \"inner\" {} }").err().map(|e| e
+                .lines()
+                .next()
+                .unwrap()
+                .to_string()),
+            Some("[line 1] Tests can only be declared at the top level.".to_string())
+        );
+        assert_eq!(
+            parse("test adds {}").err(),
+            Some("[line 1] Expect test name after 'test'.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_deep_nesting_is_an_error() {
+        let parse = |source: &str| {
+            let tokens = Scanner::new(source).scan_tokens().unwrap();
+            Parser::new(tokens).parse()
+        };
+
+        let source = format!("print {}1{};", "(".repeat(1000), ")".repeat(1000));
+        assert_eq!(
+            parse(&source).err(),
+            Some("[line 1] Too much nesting.".to_string())
+        );
+        assert!(parse(&"{".repeat(1000))
+            .err()
+            .unwrap()
+            .contains("Too much nesting."));
+        assert!(parse(&format!("print {}1;", "-".repeat(1000)))
+            .err()
+            .unwrap()
+            .contains("Too much nesting."));
+        assert!(parse(&format!("print {}1;", "-".repeat(100))).is_ok());
+        // Chains are flat in the source, however deep their trees are.
+        assert!(parse(&format!("print {}1;", "1 + ".repeat(1000))).is_ok());
+        assert!(parse(&format!("print f{};", "(1)".repeat(1000))).is_ok());
+        assert!(parse(&format!("print a{};", ".b".repeat(1000))).is_ok());
+    }
+
+    #[test]
+    fn test_missing_eof() {
+        assert!(Parser::new(vec![]).parse().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_call() {
+        let source = "readFile(\"a.txt\")(1, 2 + 3)";
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let mut parser = Parser::new(tokens);
+        let parsed_expr = parser.expression().unwrap();
+        let string_expr = parsed_expr.to_string();
+
+        assert_eq!(string_expr, "(call (call (var readFile) a.txt) 1 (+ 2 3))");
+    }
 }
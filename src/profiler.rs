@@ -0,0 +1,307 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::exception::Frame;
+use crate::interpreter::{Hook, Interpreter};
+use crate::stmt::Stmt;
+
+/// How many entries of each table the report shows.
+const REPORT_LIMIT: usize = 20;
+
+/// How often something ran and how long it took.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Timing {
+    pub count: u64,
+    pub time: Duration,
+}
+
+/// A function as the profile tells it apart from others: two functions with
+/// the same name in different files, or declared on different lines, are
+/// timed separately.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Function {
+    pub name: String,
+    pub file: String,
+    /// The line the function is declared on, or 0 for the top level.
+    pub line: usize,
+}
+
+impl Function {
+    fn of(frame: &Frame) -> Self {
+        Self {
+            name: frame.function.clone(),
+            file: frame.file.clone(),
+            line: frame.declared,
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{} ({})", self.name, self.file)
+        } else {
+            write!(f, "{} ({}:{})", self.name, self.file, self.line)
+        }
+    }
+}
+
+/// Where time is being charged until the next statement starts.
+struct Sample {
+    file: String,
+    line: usize,
+    /// Functions from the script down to the running function.
+    stack: Vec<Function>,
+    start: Instant,
+}
+
+/// Statement counts and wall time gathered while a program runs.
+///
+/// The time between one statement starting and the next one starting is
+/// charged to the first, so each line's time is its own and excludes the
+/// statements it runs on other lines, like a function body.
+#[derive(Default)]
+pub struct Profile {
+    /// Keyed by file and line.
+    pub lines: HashMap<(String, usize), Timing>,
+    /// Statements executed and time spent directly in each function.
+    pub functions: HashMap<Function, Timing>,
+    /// Time per call stack, outermost function first.
+    pub stacks: HashMap<Vec<Function>, Duration>,
+    current: Option<Sample>,
+}
+
+impl Profile {
+    fn record(&mut self, interpreter: &Interpreter, stmt: &Stmt) {
+        let now = Instant::now();
+        self.charge(now);
+
+        // A block only holds other statements, which are counted themselves.
+        if matches!(stmt, Stmt::Block { .. }) {
+            return;
+        }
+        let (Some(line), Some(frame)) = (stmt.line(), interpreter.frames().last()) else {
+            return;
+        };
+
+        let stack: Vec<Function> = interpreter.frames().iter().map(Function::of).collect();
+        self.lines
+            .entry((frame.file.clone(), line))
+            .or_default()
+            .count += 1;
+        self.functions.entry(Function::of(frame)).or_default().count += 1;
+        self.current = Some(Sample {
+            file: frame.file.clone(),
+            line,
+            stack,
+            start: now,
+        });
+    }
+
+    fn charge(&mut self, now: Instant) {
+        let Some(sample) = self.current.take() else {
+            return;
+        };
+        let elapsed = now - sample.start;
+
+        if let Some(timing) = self.lines.get_mut(&(sample.file, sample.line)) {
+            timing.time += elapsed;
+        }
+        if let Some(timing) = sample
+            .stack
+            .last()
+            .and_then(|function| self.functions.get_mut(function))
+        {
+            timing.time += elapsed;
+        }
+        *self.stacks.entry(sample.stack).or_default() += elapsed;
+    }
+
+    /// Charges the last statement, once the program has finished.
+    pub fn finish(&mut self) {
+        self.charge(Instant::now());
+    }
+
+    /// Time spent in each function including the functions it called,
+    /// counting recursive calls once.
+    pub fn inclusive(&self) -> HashMap<Function, Duration> {
+        let mut totals: HashMap<Function, Duration> = HashMap::new();
+        for (stack, time) in &self.stacks {
+            let mut seen: Vec<&Function> = vec![];
+            for function in stack {
+                if !seen.contains(&function) {
+                    seen.push(function);
+                    *totals.entry(function.clone()).or_default() += *time;
+                }
+            }
+        }
+        totals
+    }
+
+    /// The slowest lines and functions, slowest first.
+    pub fn report(&self) -> String {
+        let mut out = String::new();
+
+        let mut lines: Vec<_> = self.lines.iter().collect();
+        lines.sort_by(|a, b| b.1.time.cmp(&a.1.time).then(a.0.cmp(b.0)));
+        let _ = writeln!(out, "{:>12} {:>10}  line", "time (ms)", "count");
+        for ((file, line), timing) in lines.iter().take(REPORT_LIMIT) {
+            let _ = writeln!(
+                out,
+                "{:>12.3} {:>10}  {}:{}",
+                millis(timing.time),
+                timing.count,
+                file,
+                line
+            );
+        }
+        omitted(&mut out, lines.len());
+
+        let inclusive = self.inclusive();
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort_by(|a, b| b.1.time.cmp(&a.1.time).then(a.0.cmp(b.0)));
+        let _ = writeln!(
+            out,
+            "\n{:>12} {:>12} {:>11}  function",
+            "self (ms)", "total (ms)", "statements"
+        );
+        for (function, timing) in functions.iter().take(REPORT_LIMIT) {
+            let total = inclusive.get(*function).copied().unwrap_or_default();
+            let _ = writeln!(
+                out,
+                "{:>12.3} {:>12.3} {:>11}  {}",
+                millis(timing.time),
+                millis(total),
+                timing.count,
+                function
+            );
+        }
+        omitted(&mut out, functions.len());
+
+        out
+    }
+
+    /// One line per call stack with its time in microseconds, the folded
+    /// format flamegraph tools read.
+    pub fn folded(&self) -> String {
+        let mut stacks: Vec<_> = self
+            .stacks
+            .iter()
+            .map(|(stack, time)| {
+                let frames: Vec<String> = stack.iter().map(ToString::to_string).collect();
+                (frames.join(";"), time)
+            })
+            .collect();
+        stacks.sort();
+        stacks
+            .into_iter()
+            .map(|(stack, time)| format!("{} {}\n", stack, time.as_micros()))
+            .collect()
+    }
+}
+
+fn millis(time: Duration) -> f64 {
+    time.as_secs_f64() * 1000.0
+}
+
+fn omitted(out: &mut String, total: usize) {
+    if total > REPORT_LIMIT {
+        let _ = writeln!(out, "{:>12}  ({} more)", "...", total - REPORT_LIMIT);
+    }
+}
+
+/// Attached to an interpreter as its `Hook`, filling in a profile the
+/// caller keeps a handle to.
+pub struct Profiler(pub Rc<RefCell<Profile>>);
+
+impl Hook for Profiler {
+    fn before_statement(&mut self, interpreter: &mut Interpreter, stmt: &Stmt) {
+        self.0.borrow_mut().record(interpreter, stmt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    fn function(name: &str, line: usize) -> Function {
+        Function {
+            name: name.to_string(),
+            file: "<script>".to_string(),
+            line,
+        }
+    }
+
+    fn profile(source: &str) -> Profile {
+        let profile = Rc::new(RefCell::new(Profile::default()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_hook(Box::new(Profiler(profile.clone())));
+
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let stmts = Parser::new(tokens).parse().unwrap();
+        interpreter.interpret(stmts).unwrap();
+
+        drop(interpreter);
+        let mut profile = Rc::try_unwrap(profile).ok().unwrap().into_inner();
+        profile.finish();
+        profile
+    }
+
+    #[test]
+    fn counts_lines_and_functions() {
+        let profile = profile(
+            "fun fib(n) {\n  if (n < 2) return n;\n  return fib(n - 1) + fib(n - 2);\n}\n\
+             var i = 0;\nwhile (i < 3) {\n  fib(4);\n  i = i + 1;\n}",
+        );
+
+        let count = |line: usize| profile.lines[&("<script>".to_string(), line)].count;
+        // fib(4) makes 9 calls; 5 return early and 4 recurse further.
+        assert_eq!(count(2), 3 * (9 + 5));
+        assert_eq!(count(3), 12);
+        assert_eq!(count(5), 1);
+        assert_eq!(count(7), 3);
+        assert_eq!(profile.functions[&function("fib", 1)].count, 42 + 12);
+        assert_eq!(profile.functions[&function("<script>", 0)].count, 3 + 3 + 3);
+    }
+
+    #[test]
+    fn tells_functions_with_the_same_name_apart() {
+        let profile = profile(
+            "fun step() { var a = 1; }\nstep();\n\
+             fun step() { var b = 2; var c = 3; }\nstep();",
+        );
+
+        assert_eq!(profile.functions[&function("step", 1)].count, 1);
+        assert_eq!(profile.functions[&function("step", 3)].count, 2);
+        assert!(profile.report().contains("step (<script>:3)"));
+    }
+
+    #[test]
+    fn folds_call_stacks() {
+        let profile = profile("fun inner() { var a = 1; }\nfun outer() { inner(); }\nouter();");
+
+        let folded = profile.folded();
+        let stacks: Vec<&str> = folded
+            .lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0)
+            .collect();
+        assert_eq!(
+            stacks,
+            [
+                "<script> (<script>)",
+                "<script> (<script>);outer (<script>:2)",
+                "<script> (<script>);outer (<script>:2);inner (<script>:1)",
+            ]
+        );
+
+        let inclusive = profile.inclusive();
+        assert!(inclusive[&function("<script>", 0)] >= inclusive[&function("outer", 2)]);
+        assert!(inclusive[&function("outer", 2)] >= inclusive[&function("inner", 1)]);
+        assert!(profile.report().contains("<script>:3"));
+    }
+}
@@ -0,0 +1,249 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use crate::expr::LiteralValue;
+use crate::interpreter::Interpreter;
+use crate::parser::Parser;
+use crate::scanner::{Scanner, TokenType::*};
+use crate::Failure;
+
+const HELP: &str = "\
+Enter Lox statements to run them, or an expression to print its value.
+Input continues on the next line while braces or parentheses are open or
+a statement has no closing ';'. Ctrl-C stops input that is running.
+
+Commands:
+  :help        show this message
+  :env         list global variables
+  :load <file> run a file in this session
+  :reset       discard all definitions
+  :quit        leave the REPL (or press Ctrl-D)";
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Help,
+    Env,
+    Load(String),
+    Reset,
+    Quit,
+    Unknown(String),
+}
+
+fn parse_command(line: &str) -> Option<Command> {
+    let line = line.trim();
+    let command = line.strip_prefix(':')?;
+    let (name, argument) = match command.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (command, ""),
+    };
+
+    Some(match (name, argument) {
+        ("help", "") => Command::Help,
+        ("env", "") => Command::Env,
+        ("load", path) if !path.is_empty() => Command::Load(path.to_string()),
+        ("reset", "") => Command::Reset,
+        ("quit", "") => Command::Quit,
+        _ => Command::Unknown(line.to_string()),
+    })
+}
+
+/// Whether `source` stops partway through a statement, so the REPL should
+/// keep reading lines instead of running it.
+fn is_incomplete(source: &str) -> bool {
+    let tokens = match Scanner::new(source).scan_tokens() {
+        Ok(tokens) => tokens,
+        Err(msg) => return msg.contains("Unterminated string"),
+    };
+
+    let mut depth = 0;
+    for token in &tokens {
+        match token.token_type {
+            LeftParen | LeftBrace => depth += 1,
+            RightParen | RightBrace => depth -= 1,
+            _ => (),
+        }
+    }
+    if depth > 0 {
+        return true;
+    }
+
+    match tokens.iter().rev().find(|token| token.token_type != Eof) {
+        Some(token) if matches!(token.token_type, Semicolon | RightBrace) => false,
+        // Without a closing ';' only a bare expression is complete.
+        Some(_) => Parser::for_repl(tokens).parse().is_err(),
+        None => false,
+    }
+}
+
+/// Runs one chunk of REPL input, echoing the value of a trailing expression.
+fn eval(interpreter: &mut Interpreter, source: &str) -> Result<(), Failure> {
+    let tokens = Scanner::new(source).scan_tokens()?;
+    let stmts = Parser::for_repl(tokens).parse()?;
+
+    match interpreter.interpret_repl(stmts) {
+        Ok(Some(LiteralValue::Nil)) | Ok(None) => Ok(()),
+        Ok(Some(value)) => {
+            println!("{}", value);
+            Ok(())
+        }
+        Err(exception) => Err(exception.into()),
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    match env::var_os("JLOX_HISTORY") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => env::var_os("HOME").map(|home| PathBuf::from(home).join(".jlox_history")),
+    }
+}
+
+fn print_env(interpreter: &Interpreter) {
+    let environment = interpreter.environment.borrow();
+    let mut values = environment.values();
+    values.sort_by_key(|(name, _)| name.as_str());
+
+    for (name, value) in values {
+        println!("{} = {}", name, value);
+    }
+}
+
+/// Reads and runs input until it ends, or until it calls `exit`, which is
+/// handed back as `Failure::Exit`.
+pub fn run_prompt() -> Result<(), Failure> {
+    let mut editor = DefaultEditor::new()
+        .map_err(|err| format!("ERROR: could not start line editor: {}", err))?;
+    let history = history_path();
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
+
+    // The line editor reads Ctrl-C as a key, so the signal only arrives
+    // while input is being run, and then stops just that input.
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let handler = interrupt.clone();
+    let _ = ctrlc::set_handler(move || handler.store(true, Ordering::Relaxed));
+
+    let mut interpreter = Interpreter::new();
+    interpreter.set_interrupt(interrupt.clone());
+    let mut buffer = String::new();
+    let mut exit = None;
+
+    loop {
+        let prompt = if buffer.is_empty() { "> " } else { ". " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(format!("ERROR: could not read line: {}", err).into()),
+        };
+
+        if buffer.is_empty() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            if let Some(command) = parse_command(&line) {
+                let _ = editor.add_history_entry(line.trim());
+                interrupt.store(false, Ordering::Relaxed);
+                match command {
+                    Command::Help => println!("{}", HELP),
+                    Command::Env => print_env(&interpreter),
+                    Command::Load(path) => match fs::read_to_string(&path) {
+                        Ok(source) => match crate::run(&mut interpreter, &source) {
+                            Ok(()) => {}
+                            Err(Failure::Error(msg)) => println!("{}", msg),
+                            Err(Failure::Exit(status)) => {
+                                exit = Some(status);
+                                break;
+                            }
+                        },
+                        Err(err) => println!("Could not load '{}': {}", path, err),
+                    },
+                    Command::Reset => {
+                        interpreter = Interpreter::new();
+                        interpreter.set_interrupt(interrupt.clone());
+                    }
+                    Command::Quit => break,
+                    Command::Unknown(command) => {
+                        println!("Unknown command '{}'. Type :help for help.", command)
+                    }
+                }
+                continue;
+            }
+        }
+
+        buffer.push_str(&line);
+        buffer.push('\n');
+        // An empty line runs whatever has been typed so far.
+        if !line.trim().is_empty() && is_incomplete(&buffer) {
+            continue;
+        }
+
+        let _ = editor.add_history_entry(buffer.trim_end());
+        interrupt.store(false, Ordering::Relaxed);
+        match eval(&mut interpreter, &buffer) {
+            Ok(()) => {}
+            Err(Failure::Error(msg)) => println!("{}", msg),
+            Err(Failure::Exit(status)) => {
+                exit = Some(status);
+                break;
+            }
+        }
+        buffer.clear();
+    }
+
+    if let Some(path) = &history {
+        let _ = editor.save_history(path);
+    }
+
+    match exit {
+        Some(status) => Err(Failure::Exit(status)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_incomplete_input() {
+        assert!(is_incomplete("fun f() {"));
+        assert!(is_incomplete("print (1 +"));
+        assert!(is_incomplete("var a = 1"));
+        assert!(is_incomplete("print 1"));
+        assert!(is_incomplete("1 +"));
+        assert!(is_incomplete("print \"two\nlines"));
+
+        assert!(!is_incomplete("fun f() {\n  return 1;\n}"));
+        assert!(!is_incomplete("print 1;"));
+        assert!(!is_incomplete("1 +;"));
+        assert!(!is_incomplete("1 + 2"));
+        assert!(!is_incomplete("add(1, 2)"));
+        assert!(!is_incomplete("   "));
+    }
+
+    #[test]
+    fn parses_meta_commands() {
+        assert_eq!(parse_command("print 1;"), None);
+        assert_eq!(parse_command(" :help "), Some(Command::Help));
+        assert_eq!(
+            parse_command(":load  lib/a.lox"),
+            Some(Command::Load("lib/a.lox".to_string()))
+        );
+        assert_eq!(
+            parse_command(":load"),
+            Some(Command::Unknown(":load".to_string()))
+        );
+        assert_eq!(parse_command(":quit"), Some(Command::Quit));
+    }
+}
@@ -0,0 +1,250 @@
+use std::rc::Rc;
+
+use crate::expr::Expr;
+use crate::interner::{Symbol, SymbolMap};
+use crate::limits;
+use crate::scanner::Token;
+use crate::stmt::Stmt;
+
+/// Where a variable is stored, as worked out before a script runs.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Location {
+    /// Not resolved, so looked up by name from the innermost scope out.
+    /// Expressions typed into the debugger are left like this, as they
+    /// run in whatever scope the program is paused in.
+    #[default]
+    Unresolved,
+    /// Declared at the top level of the script, or a built-in.
+    Global,
+    /// In `slot` of the local scope `depth` levels out from the one the
+    /// variable is used in.
+    Local { depth: usize, slot: usize },
+}
+
+/// Resolves every variable in a script to where it will be stored, so
+/// locals are read by position instead of by name.
+///
+/// Scopes are static: a function sees the locals declared before it, not
+/// ones declared later in an enclosing block, which it would otherwise
+/// find by name once they existed.
+pub fn resolve(stmts: &mut [Stmt]) {
+    Resolver { scopes: vec![] }.stmts(stmts);
+}
+
+/// Whether a block of `stmts` declares any variables. Blocks that do not
+/// get no scope of their own, saving one for each pass through a loop body.
+pub(crate) fn declares(stmts: &[Stmt]) -> bool {
+    stmts.iter().any(|stmt| {
+        matches!(
+            stmt,
+            Stmt::Function { .. } | Stmt::Import { .. } | Stmt::Var { .. }
+        )
+    })
+}
+
+struct Resolver {
+    /// Each local scope's variables and their slots, innermost last. The
+    /// top level is not here, since globals are looked up by name.
+    scopes: Vec<SymbolMap<usize>>,
+}
+
+impl Resolver {
+    fn stmts(&mut self, stmts: &mut [Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn block(&mut self, stmts: &mut [Stmt]) {
+        if !declares(stmts) {
+            return self.stmts(stmts);
+        }
+
+        self.scopes.push(SymbolMap::default());
+        self.stmts(stmts);
+        self.scopes.pop();
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Block { statements } => self.block(statements),
+            Stmt::Test { body, .. } => self.block(body),
+            Stmt::Break { .. } | Stmt::Continue { .. } => {}
+            Stmt::Expression { expression } | Stmt::Print { expression } => self.expr(expression),
+            Stmt::Function { name, params, body } => {
+                // Declared first, so the function can call itself.
+                self.declare(name);
+                // Only a body still being built can be changed; one already
+                // shared by a declared function has been resolved before.
+                if let Some(body) = Rc::get_mut(body) {
+                    self.scopes.push(SymbolMap::default());
+                    for param in params.iter() {
+                        self.declare(param);
+                    }
+                    self.stmts(body);
+                    self.scopes.pop();
+                }
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(condition);
+                self.stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.stmt(else_branch);
+                }
+            }
+            Stmt::Import { name, .. } => self.declare(name),
+            Stmt::Return { value, .. } | Stmt::Throw { value, .. } => self.expr(value),
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.block(body);
+                if let Some((name, handler)) = catch {
+                    self.scopes.push(SymbolMap::default());
+                    self.declare(name);
+                    self.stmts(handler);
+                    self.scopes.pop();
+                }
+                if let Some(finally) = finally {
+                    self.block(finally);
+                }
+            }
+            Stmt::Var { name, initializer } => {
+                self.expr(initializer);
+                self.declare(name);
+            }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => {
+                self.expr(condition);
+                self.stmt(body);
+                if let Some(increment) = increment {
+                    self.expr(increment);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        limits::with_stack(|| self.resolve_expr(expr))
+    }
+
+    fn resolve_expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Assign {
+                name,
+                value,
+                location,
+            } => {
+                self.expr(value);
+                *location = self.lookup(name.lexeme.symbol());
+            }
+            Expr::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expr(callee);
+                for argument in arguments {
+                    self.expr(argument);
+                }
+            }
+            Expr::Get { object, .. } => self.expr(object),
+            Expr::Grouping { expression } => self.expr(expression),
+            Expr::Literal { .. } => {}
+            Expr::Unary { right, .. } => self.expr(right),
+            Expr::Variable { name, location } => *location = self.lookup(name.lexeme.symbol()),
+        }
+    }
+
+    /// Gives `name` the next slot of the innermost scope, or the one it
+    /// already has when it is declared again, as the environment does.
+    fn declare(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            let slot = scope.len();
+            scope.entry(name.lexeme.symbol()).or_insert(slot);
+        }
+    }
+
+    fn lookup(&self, name: Symbol) -> Location {
+        self.scopes
+            .iter()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, scope)| {
+                scope
+                    .get(&name)
+                    .map(|&slot| Location::Local { depth, slot })
+            })
+            .unwrap_or(Location::Global)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+
+    /// The name and location of every variable read in `source`, in order.
+    fn locations(source: &str) -> Vec<(String, Location)> {
+        let mut stmts = Parser::new(Scanner::new(source).scan_tokens().unwrap())
+            .parse()
+            .unwrap();
+        resolve(&mut stmts);
+
+        let mut found = vec![];
+        collect(&stmts, &mut found);
+        found
+    }
+
+    fn collect(stmts: &[Stmt], found: &mut Vec<(String, Location)>) {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Block { statements } => collect(statements, found),
+                Stmt::Function { body, .. } => collect(body, found),
+                Stmt::Print {
+                    expression: Expr::Variable { name, location },
+                } => found.push((name.lexeme.to_string(), *location)),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn resolves_locals_to_slots() {
+        assert_eq!(
+            locations(
+                "var g = 0;\nprint g;\n\
+                 fun f(a, b) {\n  var c = 1;\n  { var d = 2; print d; print c; print b; print g; }\n}"
+            ),
+            [
+                ("g".to_string(), Location::Global),
+                ("d".to_string(), Location::Local { depth: 0, slot: 0 }),
+                ("c".to_string(), Location::Local { depth: 1, slot: 2 }),
+                ("b".to_string(), Location::Local { depth: 1, slot: 1 }),
+                ("g".to_string(), Location::Global),
+            ]
+        );
+    }
+
+    #[test]
+    fn functions_see_only_earlier_locals() {
+        assert_eq!(
+            locations("{\n  var a = 1;\n  fun f() { print a; print b; }\n  var b = 2;\n}"),
+            [
+                ("a".to_string(), Location::Local { depth: 1, slot: 0 }),
+                ("b".to_string(), Location::Global),
+            ]
+        );
+    }
+}
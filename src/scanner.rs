@@ -1,44 +1,67 @@
 use std::string::String;
 use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::rc::Rc;
 use once_cell::sync::Lazy;
 
-static KEYWORDS: Lazy<HashMap<&'static str, TokenType>> = Lazy::new(|| {
+use crate::interner::Symbol;
+
+pub static KEYWORDS: Lazy<HashMap<&'static str, TokenType>> = Lazy::new(|| {
     let mut m = HashMap::new();
     m.insert("and",    And);
+    m.insert("as",     As);
+    m.insert("break",  Break);
+    m.insert("catch",  Catch);
     m.insert("class",  Class);
+    m.insert("continue", Continue);
     m.insert("else",   Else);
     m.insert("false",  False);
+    m.insert("finally", Finally);
     m.insert("for",    For);
     m.insert("fun",    Fun);
     m.insert("if",     If);
+    m.insert("import", Import);
     m.insert("nil",    Nil);
     m.insert("or",     Or);
     m.insert("print",  Print);
-    m.insert("return", Print);
+    m.insert("return", Return);
     m.insert("super",  Super);
+    m.insert("test",   Test);
     m.insert("this",   This);
+    m.insert("throw",  Throw);
     m.insert("true",   True);
+    m.insert("try",    Try);
     m.insert("var",    Var);
     m.insert("while",  While);
     m
 });
 
 pub struct Scanner {
-    source: String,
+    /// Indexed by character, so multi-byte characters are never split.
+    source: Vec<char>,
     tokens: Vec<Token>,
+    comments: Vec<Token>,
     start: usize,
     current: usize,
     line: usize,
+    line_start: usize,
+    start_line: usize,
+    start_column: usize,
 }
 
 impl Scanner {
     pub fn new(source: &str) -> Self {
         Self {
-            source: source.to_string(),
+            source: source.chars().collect(),
             tokens: vec![],
+            comments: vec![],
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            start_line: 1,
+            start_column: 1,
         }
     }
 
@@ -46,6 +69,8 @@ impl Scanner {
         let mut errors = vec![];
         while !self.is_at_end() {
             self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.current - self.line_start + 1;
             match self.scan_token() {
                 Ok(_) => (),
                 Err(msg) => errors.push(msg),
@@ -54,9 +79,10 @@ impl Scanner {
 
         self.tokens.push(Token {
             token_type: Eof,
-            lexeme: "".to_string(),
+            lexeme: "".into(),
             literal: None,
             line_number: self.line,
+            column: self.current - self.line_start + 1,
         });
 
         if !errors.is_empty() {
@@ -71,6 +97,12 @@ impl Scanner {
         Ok(self.tokens.clone())
     }
 
+    /// The `//` comments skipped by the last scan, in source order. They
+    /// never reach the parser; only tools that reprint source need them.
+    pub fn comments(&self) -> &[Token] {
+        &self.comments
+    }
+
     fn is_at_end(&mut self) -> bool {
         self.current >= self.source.len()
     }
@@ -133,12 +165,18 @@ impl Scanner {
                         }
                         self.in_advance();
                     }
+                    let text = self.substring(self.start, self.current);
+                    self.comments
+                        .push(Token::new(Comment, text.trim_end(), None, self.start_line, self.start_column));
                 } else {
                     self.add_token(Slash);
                 }
             }
             ' ' | '\r' | '\t' => {}
-            '\n' => self.line += 1,
+            '\n' => {
+                self.line += 1;
+                self.line_start = self.current;
+            }
             '"' => self.string()?,
 
             char => {
@@ -163,8 +201,8 @@ impl Scanner {
 	    self.in_advance();
 	}
 
-	let text = &self.source[self.start..self.current];
-	let token_type = KEYWORDS.get(text).copied().unwrap_or(Identifier);
+	let text = self.substring(self.start, self.current);
+	let token_type = KEYWORDS.get(text.as_str()).copied().unwrap_or(Identifier);
 
 	self.add_token(token_type)
     }
@@ -182,7 +220,7 @@ impl Scanner {
             }
         }
 
-	let substring = &self.source[self.start..self.current];
+	let substring = self.substring(self.start, self.current);
 	let value = substring.parse::<f64>();
 	match value {
 	    Ok(value) => self.add_token_alt(Number, Some(FloatValue(value))),
@@ -197,26 +235,27 @@ impl Scanner {
             return '\0';
         }
 
-        self.source.chars().nth(self.current + 1).unwrap()
+        self.source[self.current + 1]
     }
 
     fn string(&mut self) -> Result<(), String> {
         while self.peek() != '"' && !self.is_at_end() {
             if self.peek() == '\n' {
                 self.line += 1;
+                self.line_start = self.current + 1;
             }
             self.in_advance();
         }
 
         if self.is_at_end() {
-            return Err("Unterminated string".to_string());
+            return Err(format!("Unterminated string at line {}", self.start_line));
         }
 	
 	self.in_advance();
 
-        let value = &self.source[self.start + 1..self.current - 1];
+        let value = self.substring(self.start + 1, self.current - 1);
 
-        self.add_token_alt(StringLit, Some(StringValue(value.to_string())));
+        self.add_token_alt(StringLit, Some(StringValue(value)));
 
         Ok(())
     }
@@ -226,7 +265,7 @@ impl Scanner {
             return '\0';
         }
 
-        self.source.chars().nth(self.current).unwrap()
+        self.source[self.current]
     }
 
     fn char_match(&mut self, expected: char) -> bool {
@@ -234,7 +273,7 @@ impl Scanner {
             return false;
         }
 
-        if self.source.as_bytes()[self.current] as char != expected {
+        if self.source[self.current] != expected {
             false
         } else {
             self.current += 1;
@@ -243,10 +282,14 @@ impl Scanner {
     }
 
     fn in_advance(&mut self) -> char {
-        let c = self.source.as_bytes()[self.current];
+        let c = self.source[self.current];
         self.current += 1;
 
-        c as char
+        c
+    }
+
+    fn substring(&self, start: usize, end: usize) -> String {
+        self.source[start..end].iter().collect()
     }
 
     fn add_token(&mut self, token_type: TokenType) {
@@ -254,14 +297,10 @@ impl Scanner {
     }
 
     fn add_token_alt(&mut self, token_type: TokenType, literal: Option<LiteralValue>) {
-        let mut text = self.source[self.start..self.current].to_string();
+        let text = self.substring(self.start, self.current);
 
-        self.tokens.push(Token {
-            token_type,
-            lexeme: text,
-            literal,
-            line_number: self.line,
-        })
+        self.tokens
+            .push(Token::new(token_type, &text, literal, self.start_line, self.start_column))
     }
 }
 
@@ -297,22 +336,34 @@ pub enum TokenType {
 
     // Keywords
     And,
+    As,
+    Break,
+    Catch,
     Class,
+    Continue,
     Else,
     False,
+    Finally,
     Fun,
     For,
     If,
+    Import,
     Nil,
     Or,
     Print,
     Return,
     Super,
+    Test,
     This,
+    Throw,
     True,
+    Try,
     Var,
     While,
 
+    // Only found in `Scanner::comments`
+    Comment,
+
     Eof,
 }
 
@@ -322,6 +373,7 @@ impl std::fmt::Display for TokenType {
     }
 }
 
+#[allow(dead_code, clippy::enum_variant_names)]
 #[derive(Debug, Clone)]
 pub enum LiteralValue {
     IntValue(i64),
@@ -336,38 +388,121 @@ use TokenType::*;
 #[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
-    pub lexeme: String,
+    pub lexeme: Lexeme,
     pub literal: Option<LiteralValue>,
     pub line_number: usize,
+    pub column: usize,
 }
 
 impl Token {
     pub fn new(
         token_type: TokenType,
-        lexeme: String,
+        lexeme: &str,
         literal: Option<LiteralValue>,
         line_number: usize,
+        column: usize,
     ) -> Self {
+        let lexeme = match token_type {
+            Identifier => Lexeme::Name(Symbol::intern(lexeme)),
+            _ => lexeme.into(),
+        };
+
         Self {
             token_type,
             lexeme,
             literal,
             line_number,
+            column,
+        }
+    }
+}
+
+/// The source text of a token. Identifiers are interned, since variables are
+/// looked up by them; every other lexeme, numbers and comments included, is
+/// kept as its own text, so the interner only grows with distinct names.
+#[derive(Clone)]
+pub enum Lexeme {
+    Name(Symbol),
+    Text(Rc<str>),
+}
+
+impl Lexeme {
+    pub fn as_str(&self) -> &str {
+        self
+    }
+
+    /// The lexeme as a variable name. Only synthesized tokens are not
+    /// interned already.
+    pub fn symbol(&self) -> Symbol {
+        match self {
+            Lexeme::Name(symbol) => *symbol,
+            Lexeme::Text(text) => Symbol::intern(text),
+        }
+    }
+}
+
+impl Deref for Lexeme {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        match self {
+            Lexeme::Name(symbol) => symbol.as_str(),
+            Lexeme::Text(text) => text,
+        }
+    }
+}
+
+impl PartialEq for Lexeme {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Lexeme::Name(a), Lexeme::Name(b)) => a == b,
+            _ => **self == **other,
         }
     }
+}
 
-    pub fn _to_string(&self) -> String {
-        format!("{} {} {:?}", self.token_type, self.lexeme, self.literal)
+impl From<&str> for Lexeme {
+    fn from(text: &str) -> Self {
+        Lexeme::Text(text.into())
+    }
+}
+
+impl From<Symbol> for Lexeme {
+    fn from(symbol: Symbol) -> Self {
+        Lexeme::Name(symbol)
+    }
+}
+
+impl fmt::Display for Lexeme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+impl fmt::Debug for Lexeme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", &**self)
+    }
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {} ", self.token_type, self.lexeme)?;
+        match &self.literal {
+            Some(IntValue(x)) => write!(f, "{}", x),
+            Some(FloatValue(x)) => write!(f, "{}", x),
+            Some(StringValue(s)) | Some(IdentifierValue(s)) => write!(f, "{}", s),
+            None => write!(f, "null"),
+        }
     }
 }
 
 fn is_digit(c: char) -> bool {
-    c as u8 >= b'0' && c as u8 <= b'9'
+    c.is_ascii_digit()
 }
 
 fn is_alpha(c: char) -> bool {
-    let char = c as u8;
-    char >= b'a' && char <= b'z' || char >= b'A' && char <= b'Z' || char == b'_'
+    c.is_ascii_alphabetic() || c == '_'
 }
 
 fn is_alpha_numeric(c: char) -> bool {
@@ -378,6 +513,21 @@ fn is_alpha_numeric(c: char) -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn only_identifiers_are_interned() {
+        let mut scanner = Scanner::new("var total = 12.5 + \"s\"; // note");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        let interned: Vec<&str> = tokens
+            .iter()
+            .chain(scanner.comments())
+            .filter(|token| matches!(token.lexeme, Lexeme::Name(_)))
+            .map(|token| token.lexeme.as_str())
+            .collect();
+        assert_eq!(interned, ["total"]);
+        assert_eq!(tokens[3].lexeme, Lexeme::from("12.5"));
+    }
+
     #[test]
     fn handle_char_tokens() {
         let source = "((  )) {}";
@@ -510,4 +660,54 @@ mod tests {
 	assert_eq!(scanner.tokens[11].token_type, Semicolon);
 	assert_eq!(scanner.tokens[12].token_type, Eof);
     }
+
+    #[test]
+    fn handle_loop_control_keywords() {
+        let source = "while true { break; continue; }";
+        let mut scanner = Scanner::new(source);
+        let _ = scanner.scan_tokens();
+
+        assert_eq!(scanner.tokens.len(), 9);
+	assert_eq!(scanner.tokens[3].token_type, Break);
+	assert_eq!(scanner.tokens[5].token_type, Continue);
+    }
+
+    #[test]
+    fn handle_token_positions() {
+        let source = "var a = \"x\ny\";\n  print a;";
+        let mut scanner = Scanner::new(source);
+        let _ = scanner.scan_tokens();
+
+        let positions: Vec<(usize, usize)> = scanner.tokens.iter()
+            .map(|token| (token.line_number, token.column))
+            .collect();
+	assert_eq!(positions, vec![(1, 1), (1, 5), (1, 7), (1, 9), (2, 3), (3, 3), (3, 9), (3, 10), (3, 11)]);
+	assert_eq!(scanner.tokens[3].to_string(), "StringLit \"x\ny\" x\ny");
+    }
+
+    #[test]
+    fn handle_comments() {
+        let mut scanner = Scanner::new("// head\nvar a; // tail  \n");
+        let _ = scanner.scan_tokens();
+
+        assert_eq!(scanner.tokens.len(), 4);
+        let comments: Vec<(&str, usize, usize)> = scanner.comments().iter()
+            .map(|c| (c.lexeme.as_str(), c.line_number, c.column))
+            .collect();
+        assert_eq!(comments, vec![("// head", 1, 1), ("// tail", 2, 8)]);
+    }
+
+    #[test]
+    fn handle_multibyte_characters() {
+        let mut scanner = Scanner::new("print \"naïve ☃\"; // ünï\nvar x = 1;");
+        let tokens = scanner.scan_tokens().unwrap();
+
+        assert_eq!(tokens[1].lexeme.as_str(), "\"naïve ☃\"");
+        assert_eq!(tokens[2].column, 16);
+        assert_eq!(scanner.comments()[0].lexeme.as_str(), "// ünï");
+
+        for source in ["€", "İ", "š1", "\"☃"] {
+            assert!(Scanner::new(source).scan_tokens().is_err(), "{}", source);
+        }
+    }
 }
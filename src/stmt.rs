@@ -1,8 +1,157 @@
+use std::fmt;
+use std::rc::Rc;
+
 use crate::expr::Expr;
 use crate::scanner::Token;
 
 pub enum Stmt {
-    Expression { expression: Expr },
-    Print { expression: Expr },
-    Var { name: Token, initializer: Expr },
+    Block {
+        statements: Vec<Stmt>,
+    },
+    Break {
+        keyword: Token,
+    },
+    Continue {
+        keyword: Token,
+    },
+    Expression {
+        expression: Expr,
+    },
+    Function {
+        name: Token,
+        params: Vec<Token>,
+        body: Rc<Vec<Stmt>>,
+    },
+    If {
+        condition: Expr,
+        then_branch: Box<Stmt>,
+        else_branch: Option<Box<Stmt>>,
+    },
+    Import {
+        path: Token,
+        name: Token,
+    },
+    Print {
+        expression: Expr,
+    },
+    Return {
+        keyword: Token,
+        value: Expr,
+    },
+    /// `test "name" { ... }`, only allowed at the top level. Running a
+    /// script skips these; `jlox test` runs each one on its own.
+    Test {
+        name: Token,
+        body: Vec<Stmt>,
+    },
+    Throw {
+        keyword: Token,
+        value: Expr,
+    },
+    Try {
+        body: Vec<Stmt>,
+        catch: Option<(Token, Vec<Stmt>)>,
+        finally: Option<Vec<Stmt>>,
+    },
+    Var {
+        name: Token,
+        initializer: Expr,
+    },
+    /// `increment` runs after every iteration, including ones cut short by
+    /// `continue`, so `for` loops can be expressed without desugaring it
+    /// into the body.
+    While {
+        condition: Expr,
+        body: Box<Stmt>,
+        increment: Option<Expr>,
+    },
+}
+
+impl Stmt {
+    /// The line the statement starts on. Only an empty block has none.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Stmt::Block { statements } => statements.first().and_then(Stmt::line),
+            Stmt::Break { keyword }
+            | Stmt::Continue { keyword }
+            | Stmt::Return { keyword, .. }
+            | Stmt::Throw { keyword, .. } => Some(keyword.line_number),
+            Stmt::Expression { expression } | Stmt::Print { expression } => Some(expression.line()),
+            Stmt::Function { name, .. } | Stmt::Test { name, .. } | Stmt::Var { name, .. } => {
+                Some(name.line_number)
+            }
+            Stmt::If { condition, .. } | Stmt::While { condition, .. } => Some(condition.line()),
+            Stmt::Import { path, .. } => Some(path.line_number),
+            Stmt::Try { body, .. } => body.first().and_then(Stmt::line),
+        }
+    }
+}
+
+fn write_block(f: &mut fmt::Formatter, name: &str, statements: &[Stmt]) -> fmt::Result {
+    write!(f, "({}", name)?;
+    for stmt in statements {
+        write!(f, " {}", stmt)?;
+    }
+    write!(f, ")")
+}
+
+impl fmt::Display for Stmt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stmt::Block { statements } => write_block(f, "block", statements),
+            Stmt::Break { .. } => write!(f, "(break)"),
+            Stmt::Continue { .. } => write!(f, "(continue)"),
+            Stmt::Expression { expression } => write!(f, "(; {})", expression),
+            Stmt::Function { name, params, body } => {
+                write!(f, "(fun {} (", name.lexeme)?;
+                let params: Vec<&str> = params.iter().map(|p| p.lexeme.as_str()).collect();
+                write!(f, "{})", params.join(" "))?;
+                for stmt in body.iter() {
+                    write!(f, " {}", stmt)?;
+                }
+                write!(f, ")")
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => match else_branch {
+                Some(else_branch) => {
+                    write!(f, "(if {} {} {})", condition, then_branch, else_branch)
+                }
+                None => write!(f, "(if {} {})", condition, then_branch),
+            },
+            Stmt::Import { path, name } => write!(f, "(import {} {})", path.lexeme, name.lexeme),
+            Stmt::Print { expression } => write!(f, "(print {})", expression),
+            Stmt::Return { value, .. } => write!(f, "(return {})", value),
+            Stmt::Test { name, body } => write_block(f, &format!("test {}", name.lexeme), body),
+            Stmt::Throw { value, .. } => write!(f, "(throw {})", value),
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                write!(f, "(try ")?;
+                write_block(f, "block", body)?;
+                if let Some((name, handler)) = catch {
+                    write!(f, " ")?;
+                    write_block(f, &format!("catch {}", name.lexeme), handler)?;
+                }
+                if let Some(finally) = finally {
+                    write!(f, " ")?;
+                    write_block(f, "finally", finally)?;
+                }
+                write!(f, ")")
+            }
+            Stmt::Var { name, initializer } => write!(f, "(var {} {})", name.lexeme, initializer),
+            Stmt::While {
+                condition,
+                body,
+                increment,
+            } => match increment {
+                Some(increment) => write!(f, "(while {} {} {})", condition, body, increment),
+                None => write!(f, "(while {} {})", condition, body),
+            },
+        }
+    }
 }
@@ -0,0 +1,255 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::exception::Exception;
+use crate::formatter::collect_files;
+use crate::interpreter::Interpreter;
+use crate::natives::Capabilities;
+use crate::parser::Parser;
+use crate::resolver;
+use crate::scanner::Scanner;
+use crate::stmt::Stmt;
+
+const USAGE: &str = "Usage: jlox test [path...]";
+
+/// Files that hold tests, when found by walking a directory.
+const SUFFIX: &str = "_test.lox";
+
+/// `jlox test`: runs the `test` blocks in the given files, and in the
+/// `*_test.lox` files below the given directories (the current one by
+/// default), and fails if any of them do.
+pub fn run(args: &[String]) -> Result<(), String> {
+    if args.iter().any(|arg| arg.starts_with("--")) {
+        return Err(USAGE.to_string());
+    }
+    let paths = match args {
+        [] => vec![".".to_string()],
+        _ => args.to_vec(),
+    };
+
+    let mut files = vec![];
+    for path in &paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            let mut found = vec![];
+            collect_files(path, &mut found)?;
+            found.retain(|file| file.to_string_lossy().ends_with(SUFFIX));
+            files.extend(found);
+        } else {
+            files.push(path.to_path_buf());
+        }
+    }
+
+    let summary = run_files(&files, &mut io::stdout());
+    match summary.failed {
+        0 => Ok(()),
+        failed => Err(format!("{} test(s) failed", failed)),
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Summary {
+    pub passed: usize,
+    /// Failed tests, plus files that could not be loaded at all.
+    pub failed: usize,
+}
+
+/// Runs every test in `files`, each in an interpreter of its own, and
+/// reports on them to `out`.
+pub fn run_files(files: &[PathBuf], out: &mut impl Write) -> Summary {
+    let mut summary = Summary::default();
+    let mut failures = vec![];
+
+    for file in files {
+        match run_file(file, out) {
+            Ok((passed, failed)) => {
+                summary.passed += passed;
+                summary.failed += failed.len();
+                failures.extend(failed);
+            }
+            Err(err) => {
+                let _ = writeln!(out, "error: {}", err);
+                summary.failed += 1;
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        let _ = writeln!(out, "\nfailures:");
+        for failure in &failures {
+            let _ = write!(out, "\n{}", failure);
+        }
+    }
+    let _ = writeln!(
+        out,
+        "\ntest result: {}. {} passed; {} failed",
+        if summary.failed == 0 { "ok" } else { "FAILED" },
+        summary.passed,
+        summary.failed
+    );
+    summary
+}
+
+/// Runs the tests in one file, returning how many passed and the reports
+/// for those that failed.
+fn run_file(file: &Path, out: &mut impl Write) -> Result<(usize, Vec<String>), String> {
+    let source = fs::read_to_string(file)
+        .map_err(|err| format!("could not read '{}': {}", file.display(), err))?;
+    let tokens = Scanner::new(&source)
+        .scan_tokens()
+        .map_err(|err| format!("{}:\n{}", file.display(), err))?;
+    let mut stmts = Parser::new(tokens)
+        .parse()
+        .map_err(|err| format!("{}:\n{}", file.display(), err))?;
+    resolver::resolve(&mut stmts);
+
+    let tests: Vec<(&str, &[Stmt])> = stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Test { name, body } => Some((name.lexeme.trim_matches('"'), body.as_slice())),
+            _ => None,
+        })
+        .collect();
+    let _ = writeln!(
+        out,
+        "running {} test(s) from {}",
+        tests.len(),
+        file.display()
+    );
+
+    let mut passed = 0;
+    let mut failures = vec![];
+    for (name, body) in tests {
+        let output = Rc::new(RefCell::new(vec![]));
+        let mut interpreter = Interpreter::with_capabilities(Capabilities::all(), vec![]);
+        interpreter.set_file(file);
+        interpreter.set_output(output.clone());
+
+        // A test that calls `exit` ends there, passing if the status is 0.
+        match interpreter.interpret_test(&stmts, body) {
+            Ok(()) | Err(Exception { exit: Some(0), .. }) => {
+                let _ = writeln!(out, "test {} ... ok", name);
+                passed += 1;
+            }
+            Err(exception) => {
+                let _ = writeln!(out, "test {} ... FAILED", name);
+                let output = String::from_utf8_lossy(&output.borrow()).to_string();
+                failures.push(failure(file, name, &exception, &output));
+            }
+        }
+    }
+
+    Ok((passed, failures))
+}
+
+/// The error a test failed with, the source line it came from and
+/// whatever the test printed.
+fn failure(file: &Path, name: &str, exception: &Exception, output: &str) -> String {
+    let mut report = format!(
+        "---- {}: {} ----\n{}\n",
+        file.display(),
+        name,
+        exception.message()
+    );
+
+    if let Some(frame) = exception.trace.first() {
+        let source = fs::read_to_string(&frame.file).unwrap_or_default();
+        let line = source.lines().nth(frame.line.saturating_sub(1));
+        report.push_str(&format!("  --> {}:{}\n", frame.file, frame.line));
+        if let Some(line) = line {
+            report.push_str(&format!("{} | {}\n", frame.line, line.trim_end()));
+        }
+    }
+
+    if !output.is_empty() {
+        report.push_str(&format!("output:\n{}", output));
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_each_test_in_isolation() {
+        let path = std::env::temp_dir().join("jlox_testing_math_test.lox");
+        fs::write(
+            &path,
+            "var count = 0;\n\
+             fun add(a, b) { return a + b; }\n\
+             test \"adds\" {\n  count = count + 1;\n  assert(add(1, 2) == 3, \"1 + 2\");\n}\n\
+             test \"starts fresh\" {\n  count = count + 1;\n  assert(count == 1, count);\n}\n\
+             test \"fails\" {\n  print \"checking\";\n  assert(add(1, 1) == 3, \"1 + 1\");\n}\n",
+        )
+        .unwrap();
+
+        let mut out = vec![];
+        let summary = run_files(std::slice::from_ref(&path), &mut out);
+        let out = String::from_utf8(out).unwrap();
+
+        assert_eq!(
+            summary,
+            Summary {
+                passed: 2,
+                failed: 1
+            }
+        );
+        let file = path.display();
+        assert_eq!(
+            out,
+            format!(
+                "running 3 test(s) from {file}\n\
+                 test adds ... ok\ntest starts fresh ... ok\ntest fails ... FAILED\n\
+                 \nfailures:\n\n\
+                 ---- {file}: fails ----\nAssertion failed: 1 + 1\n  --> {file}:13\n\
+                 13 |   assert(add(1, 1) == 3, \"1 + 1\");\n\
+                 output:\nchecking\n\
+                 \ntest result: FAILED. 2 passed; 1 failed\n"
+            )
+        );
+    }
+
+    #[test]
+    fn exit_ends_only_the_test_that_calls_it() {
+        let path = std::env::temp_dir().join("jlox_testing_exit_test.lox");
+        fs::write(
+            &path,
+            "test \"done early\" {\n  exit(0);\n  assert(false, \"unreachable\");\n}\n\
+             test \"gives up\" {\n  exit(2);\n}\n\
+             test \"still runs\" {\n  assert(true, \"fine\");\n}\n",
+        )
+        .unwrap();
+
+        let mut out = vec![];
+        let summary = run_files(std::slice::from_ref(&path), &mut out);
+        let out = String::from_utf8(out).unwrap();
+
+        assert_eq!(
+            summary,
+            Summary {
+                passed: 2,
+                failed: 1
+            }
+        );
+        assert!(out.contains("test gives up ... FAILED"));
+        assert!(out.contains("Exited with status 2"));
+    }
+
+    #[test]
+    fn reports_files_that_do_not_parse() {
+        let path = std::env::temp_dir().join("jlox_testing_broken_test.lox");
+        fs::write(&path, "test \"broken\" { print ; }").unwrap();
+
+        let mut out = vec![];
+        let summary = run_files(&[path], &mut out);
+
+        assert_eq!(summary.failed, 1);
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("[line 1] Expected expression"));
+    }
+}
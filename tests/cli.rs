@@ -0,0 +1,24 @@
+use std::process::Command;
+use std::{env, fs};
+
+#[test]
+fn exit_status_comes_after_the_coverage_report() {
+    let dir = env::temp_dir().join(format!("jlox-cli-exit-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("exit.lox");
+    let lcov = dir.join("c.info");
+    fs::write(&script, "print 1;\nexit(3);\nprint 2;\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_jlox"))
+        .arg(format!("--coverage={}", lcov.display()))
+        .arg(&script)
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    let lcov = fs::read_to_string(&lcov).unwrap();
+    assert!(lcov.contains("DA:2,1"), "{}", lcov);
+    assert!(lcov.contains("DA:3,0"), "{}", lcov);
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,235 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Command, Stdio};
+use std::{env, fs};
+
+use serde_json::{json, Value};
+
+const PROGRAM: &str = "fun add(a, b) {
+    var sum = a + b;
+    return sum;
+}
+var x = add(1, 2);
+print x;
+";
+
+fn frame(message: &Value) -> String {
+    let body = message.to_string();
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+fn read_frames(output: impl Read) -> Vec<Value> {
+    let mut reader = BufReader::new(output);
+    let mut messages = vec![];
+    loop {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).unwrap() == 0 {
+                return messages;
+            }
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length: ") {
+                length = value.parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        messages.push(serde_json::from_slice(&body).unwrap());
+    }
+}
+
+/// Sends `requests` to a `jlox dap` process and collects everything it
+/// sends back until it exits.
+fn session(requests: &[(&str, Value)]) -> Vec<Value> {
+    let script: String = requests
+        .iter()
+        .enumerate()
+        .map(|(seq, (command, arguments))| {
+            frame(&json!({
+                "seq": seq + 1,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            }))
+        })
+        .collect();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_jlox"))
+        .arg("dap")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(script.as_bytes())
+        .unwrap();
+    let messages = read_frames(child.stdout.take().unwrap());
+    assert!(child.wait().unwrap().success());
+    messages
+}
+
+#[test]
+fn replays_scripted_session() {
+    let path = env::temp_dir().join(format!("jlox-dap-{}.lox", std::process::id()));
+    fs::write(&path, PROGRAM).unwrap();
+    let program = path.to_string_lossy().to_string();
+
+    let requests = [
+        ("initialize", json!({ "adapterID": "jlox" })),
+        ("launch", json!({ "program": program })),
+        (
+            "setBreakpoints",
+            json!({ "source": { "path": program }, "breakpoints": [{ "line": 3 }] }),
+        ),
+        ("configurationDone", json!({})),
+        ("threads", json!({})),
+        ("stackTrace", json!({ "threadId": 1 })),
+        ("scopes", json!({ "frameId": 0 })),
+        ("variables", json!({ "variablesReference": 1 })),
+        ("evaluate", json!({ "expression": "sum * 2", "frameId": 0 })),
+        ("continue", json!({ "threadId": 1 })),
+        ("disconnect", json!({})),
+    ];
+    let messages = session(&requests);
+    fs::remove_file(&path).unwrap();
+
+    let response = |command: &str| {
+        messages
+            .iter()
+            .find(|m| m["type"] == "response" && m["command"] == command)
+            .unwrap_or_else(|| panic!("no response to {}", command))
+    };
+    let events: Vec<&str> = messages
+        .iter()
+        .filter(|m| m["type"] == "event")
+        .map(|m| m["event"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        events,
+        ["initialized", "stopped", "output", "exited", "terminated"]
+    );
+    for (seq, message) in messages.iter().enumerate() {
+        assert_eq!(message["seq"], seq + 1);
+        if message["type"] == "response" {
+            assert_eq!(message["success"], true, "{}", message);
+        }
+    }
+
+    let stopped = messages.iter().find(|m| m["event"] == "stopped").unwrap();
+    assert_eq!(stopped["body"]["reason"], "breakpoint");
+    assert_eq!(
+        response("setBreakpoints")["body"]["breakpoints"],
+        json!([{ "verified": true, "line": 3 }])
+    );
+
+    let frames = &response("stackTrace")["body"]["stackFrames"];
+    assert_eq!(frames[0]["name"], "add");
+    assert_eq!(frames[0]["line"], 3);
+    assert_eq!(frames[1]["name"], "<script>");
+    assert_eq!(frames[1]["line"], 5);
+
+    let scopes = &response("scopes")["body"]["scopes"];
+    assert_eq!(scopes[0]["name"], "Locals");
+    assert_eq!(scopes[1]["name"], "Globals");
+
+    assert_eq!(
+        response("variables")["body"]["variables"],
+        json!([
+            { "name": "a", "value": "1", "type": "Number", "variablesReference": 0 },
+            { "name": "b", "value": "2", "type": "Number", "variablesReference": 0 },
+            { "name": "sum", "value": "3", "type": "Number", "variablesReference": 0 },
+        ])
+    );
+    assert_eq!(response("evaluate")["body"]["result"], "6");
+
+    let output = messages.iter().find(|m| m["event"] == "output").unwrap();
+    assert_eq!(output["body"]["output"], "3\n");
+    let exited = messages.iter().find(|m| m["event"] == "exited").unwrap();
+    assert_eq!(exited["body"]["exitCode"], 0);
+}
+
+#[test]
+fn reports_the_status_passed_to_exit() {
+    let path = env::temp_dir().join(format!("jlox-dap-exit-{}.lox", std::process::id()));
+    fs::write(&path, "print 1;\nexit(3);\nprint 2;\n").unwrap();
+    let program = path.to_string_lossy().to_string();
+
+    let messages = session(&[
+        ("initialize", json!({ "adapterID": "jlox" })),
+        ("launch", json!({ "program": program })),
+        ("configurationDone", json!({})),
+        ("disconnect", json!({})),
+    ]);
+    fs::remove_file(&path).unwrap();
+
+    let events: Vec<&str> = messages
+        .iter()
+        .filter(|m| m["type"] == "event")
+        .map(|m| m["event"].as_str().unwrap())
+        .collect();
+    assert_eq!(events, ["initialized", "output", "exited", "terminated"]);
+    let exited = messages.iter().find(|m| m["event"] == "exited").unwrap();
+    assert_eq!(exited["body"]["exitCode"], 3);
+}
+
+#[test]
+fn keeps_breakpoints_per_source() {
+    let dir = env::temp_dir().join(format!("jlox-dap-sources-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let main = dir.join("main.lox");
+    let helpers = dir.join("helpers.lox");
+    fs::write(
+        &main,
+        "import \"helpers.lox\" as helpers;\nvar x = helpers.twice(2);\nprint x;\n",
+    )
+    .unwrap();
+    fs::write(
+        &helpers,
+        "// Helpers.\nfun twice(n) {\n  var doubled = n * 2;\n  return doubled;\n}\n",
+    )
+    .unwrap();
+    let main = main.to_string_lossy().to_string();
+    let helpers = helpers.to_string_lossy().to_string();
+
+    let messages = session(&[
+        ("initialize", json!({ "adapterID": "jlox" })),
+        ("launch", json!({ "program": main })),
+        (
+            "setBreakpoints",
+            json!({ "source": { "path": helpers }, "breakpoints": [{ "line": 4 }] }),
+        ),
+        (
+            "setBreakpoints",
+            json!({ "source": { "path": main }, "breakpoints": [{ "line": 3 }] }),
+        ),
+        ("configurationDone", json!({})),
+        ("stackTrace", json!({ "threadId": 1 })),
+        ("continue", json!({ "threadId": 1 })),
+        ("stackTrace", json!({ "threadId": 1 })),
+        ("continue", json!({ "threadId": 1 })),
+        ("disconnect", json!({})),
+    ]);
+    fs::remove_dir_all(&dir).unwrap();
+
+    let stops: Vec<(String, u64)> = messages
+        .iter()
+        .filter(|m| m["type"] == "response" && m["command"] == "stackTrace")
+        .map(|m| {
+            let top = &m["body"]["stackFrames"][0];
+            (
+                top["name"].as_str().unwrap().to_string(),
+                top["line"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        stops,
+        [("twice".to_string(), 4), ("<script>".to_string(), 3)]
+    );
+}
@@ -0,0 +1,67 @@
+//! Replays the inputs kept under `fuzz/corpus` through the scanner, the
+//! parser and the interpreter, so crashes the fuzzers found stay fixed
+//! without needing `cargo fuzz` (or a nightly toolchain) to check.
+
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::thread;
+
+use jlox::interpreter::Interpreter;
+use jlox::natives::Capabilities;
+use jlox::parser::Parser;
+use jlox::scanner::Scanner;
+
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+fn corpus() -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/corpus");
+    let mut inputs = vec![];
+    for target in fs::read_dir(root).unwrap() {
+        for input in fs::read_dir(target.unwrap().path()).unwrap() {
+            inputs.push(input.unwrap().path());
+        }
+    }
+    inputs.sort();
+    inputs
+}
+
+/// Runs `source` as far as it gets. Errors are fine; only panics and stack
+/// overflows, which abort the test binary, count as failures.
+fn replay(source: String) -> thread::Result<()> {
+    thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            let Ok(tokens) = Scanner::new(&source).scan_tokens() else {
+                return;
+            };
+            let Ok(stmts) = Parser::new(tokens).parse() else {
+                return;
+            };
+            let mut interpreter =
+                Interpreter::with_capabilities(Capabilities { io: false }, vec![]);
+            interpreter.set_output(Rc::new(RefCell::new(io::sink())));
+            let _ = interpreter.interpret(stmts);
+        })
+        .unwrap()
+        .join()
+}
+
+#[test]
+fn corpus_does_not_crash() {
+    let inputs = corpus();
+    assert!(!inputs.is_empty(), "no fuzz corpus found");
+
+    let crashed: Vec<String> = inputs
+        .iter()
+        .filter(|input| {
+            let source = String::from_utf8_lossy(&fs::read(input).unwrap()).to_string();
+            replay(source).is_err()
+        })
+        .map(|input| input.display().to_string())
+        .collect();
+
+    assert!(crashed.is_empty(), "crashed on {:?}", crashed);
+}
@@ -0,0 +1,199 @@
+//! Runs every `tests/**/*.lox` script through `jlox` and checks it against
+//! the expectations in its comments, following the layout of the Crafting
+//! Interpreters test suite:
+//!
+//! - `// expect: <line>` is the next line the script prints.
+//! - `// expect runtime error: <message>` means the script stops with that
+//!   error, raised on the line the comment is on.
+//! - `// Error...: <message>`, optionally starting with `[line N]` or
+//!   `[java line N]`, means the script fails to compile with that error.
+//!   Expectations for the C implementation, `[c line N]`, are skipped.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Debug, Default)]
+struct Expectations {
+    output: Vec<String>,
+    /// The message and the line it was raised on.
+    runtime_error: Option<(String, usize)>,
+    compile_errors: Vec<(String, usize)>,
+}
+
+fn parse(source: &str) -> Expectations {
+    let mut expectations = Expectations::default();
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let Some((_, comment)) = line.split_once("// ") else {
+            continue;
+        };
+
+        if let Some(output) = comment.strip_prefix("expect: ") {
+            expectations.output.push(output.to_string());
+        } else if let Some(message) = comment.strip_prefix("expect runtime error: ") {
+            expectations.runtime_error = Some((message.to_string(), line_number));
+        } else if let Some(error) = compile_error(comment, line_number) {
+            expectations.compile_errors.push(error);
+        }
+    }
+
+    expectations
+}
+
+fn compile_error(comment: &str, line_number: usize) -> Option<(String, usize)> {
+    let (comment, line_number) = match comment.strip_prefix('[') {
+        Some(rest) => {
+            let (tag, rest) = rest.split_once("] ")?;
+            let number = tag
+                .strip_prefix("java line ")
+                .or(tag.strip_prefix("line "))?;
+            (rest, number.parse().ok()?)
+        }
+        None => (comment, line_number),
+    };
+
+    let (_, message) = comment.strip_prefix("Error")?.split_once(": ")?;
+    Some((message.to_string(), line_number))
+}
+
+/// What went wrong with one script, or nothing if it passed.
+fn check(path: &Path) -> Vec<String> {
+    let source = fs::read_to_string(path).unwrap();
+    let expected = parse(&source);
+
+    let run = Command::new(env!("CARGO_BIN_EXE_jlox"))
+        .arg(path)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&run.stdout);
+    let stderr = String::from_utf8_lossy(&run.stderr);
+    let mut failures = vec![];
+
+    let output: Vec<&str> = stdout.lines().collect();
+    if output != expected.output {
+        failures.push(diff(&expected.output, &output));
+    }
+
+    let fails = !expected.compile_errors.is_empty() || expected.runtime_error.is_some();
+    if fails == run.status.success() {
+        failures.push(format!(
+            "expected the script to {}, but it exited with {}",
+            if fails { "fail" } else { "succeed" },
+            run.status
+        ));
+    }
+
+    for (message, line) in &expected.compile_errors {
+        let reported = stderr
+            .lines()
+            .any(|error| error.contains(&format!("line {}", line)) && error.contains(message));
+        if !reported {
+            failures.push(format!(
+                "expected compile error on line {}: {}\n  stderr:\n{}",
+                line, message, stderr
+            ));
+        }
+    }
+
+    if let Some((message, line)) = &expected.runtime_error {
+        // The first line is the error itself, the next the innermost frame.
+        let mut errors = stderr.lines().skip_while(|line| *line == "ERROR:");
+        let error = errors.next().unwrap_or_default();
+        let frame = errors.next().unwrap_or_default();
+        if !error.contains(message.as_str()) || !frame.ends_with(&format!(":{})", line)) {
+            failures.push(format!(
+                "expected runtime error on line {}: {}\n  stderr:\n{}",
+                line, message, stderr
+            ));
+        }
+    }
+
+    failures
+}
+
+fn diff(expected: &[String], actual: &[&str]) -> String {
+    let mut out = String::from("output differs:");
+    for i in 0..expected.len().max(actual.len()) {
+        let expected = expected.get(i).map(String::as_str);
+        let actual = actual.get(i).copied();
+        match (expected, actual) {
+            (Some(e), Some(a)) if e == a => out.push_str(&format!("\n    {}", e)),
+            _ => {
+                if let Some(e) = expected {
+                    out.push_str(&format!("\n  - {}", e));
+                }
+                if let Some(a) = actual {
+                    out.push_str(&format!("\n  + {}", a));
+                }
+            }
+        }
+    }
+    out
+}
+
+fn collect(dir: &Path, scripts: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            collect(&entry, scripts);
+        } else if entry.extension().is_some_and(|ext| ext == "lox") {
+            scripts.push(entry);
+        }
+    }
+}
+
+#[test]
+fn golden_files() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let mut scripts = vec![];
+    collect(&root, &mut scripts);
+    assert!(
+        !scripts.is_empty(),
+        "no scripts found under {}",
+        root.display()
+    );
+
+    let mut failed = 0;
+    for script in &scripts {
+        let failures = check(script);
+        if !failures.is_empty() {
+            failed += 1;
+            let name = script.strip_prefix(&root).unwrap_or(script);
+            eprintln!("FAIL {}", name.display());
+            for failure in failures {
+                eprintln!("  {}", failure);
+            }
+        }
+    }
+
+    assert_eq!(failed, 0, "{} of {} scripts failed", failed, scripts.len());
+}
+
+#[test]
+fn parses_annotations() {
+    let expected = parse(
+        "print 1; // expect: 1\n\
+         // expect runtime error: Boom.\n\
+         var = 1; // Error at '=': Expect variable name.\n\
+         // [line 7] Error at end: Expect ';'.\n\
+         // [java line 8] Error: Java only.\n\
+         // [c line 9] Error: C only.\n",
+    );
+
+    assert_eq!(expected.output, ["1"]);
+    assert_eq!(expected.runtime_error, Some(("Boom.".to_string(), 2)));
+    assert_eq!(
+        expected.compile_errors,
+        [
+            ("Expect variable name.".to_string(), 3),
+            ("Expect ';'.".to_string(), 7),
+            ("Java only.".to_string(), 8),
+        ]
+    );
+}